@@ -0,0 +1,80 @@
+use std::error::Error;
+use std::sync::Arc;
+use tracing::info;
+
+use crate::llm_integration::LLMClient;
+
+/// Name of the only tier when no cascade is configured.
+pub const DEFAULT_TIER: &str = "default";
+
+pub struct ModelTier {
+    pub name: String,
+    pub client: Arc<LLMClient>,
+}
+
+/// LLM clients ordered from cheapest to most capable. Requests start at the
+/// first tier and move up when the verifier rejects an answer.
+pub struct ModelCascade {
+    tiers: Vec<ModelTier>,
+}
+
+impl ModelCascade {
+    /// Tiers come from `LLM_CASCADE_TIERS` (comma-separated names, cheapest
+    /// first); each tier reads `LLM_<TIER>_BACKEND`, `LLM_<TIER>_MODEL`,
+    /// `LLM_<TIER>_API_ENDPOINTS` and `LLM_<TIER>_API_KEY`. Without it there
+    /// is a single tier configured from the plain `LLM_*` variables.
+    pub async fn from_env() -> Result<Self, Box<dyn Error>> {
+        let names: Vec<String> = std::env::var("LLM_CASCADE_TIERS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .collect();
+
+        if names.is_empty() {
+            let client = LLMClient::new().await?;
+            return Ok(Self::new(vec![ModelTier { name: DEFAULT_TIER.to_string(), client: Arc::new(client) }]));
+        }
+
+        let mut tiers = Vec::with_capacity(names.len());
+        for name in names {
+            let client = LLMClient::for_tier(Some(&name)).await?;
+            tiers.push(ModelTier { name, client: Arc::new(client) });
+        }
+        info!("Model cascade: {}", tiers.iter().map(|tier| tier.name.as_str()).collect::<Vec<_>>().join(" -> "));
+        Ok(Self::new(tiers))
+    }
+
+    /// Panics if `tiers` is empty.
+    pub fn new(tiers: Vec<ModelTier>) -> Self {
+        assert!(!tiers.is_empty(), "a model cascade needs at least one tier");
+        Self { tiers }
+    }
+
+    /// The first, cheapest tier. Streaming, explain and cache keys use it.
+    pub fn primary(&self) -> &LLMClient {
+        &self.tiers[0].client
+    }
+
+    /// The primary client, for work that outlives the cascade lock.
+    pub fn shared_primary(&self) -> Arc<LLMClient> {
+        self.tiers[0].client.clone()
+    }
+
+    pub fn primary_name(&self) -> &str {
+        &self.tiers[0].name
+    }
+
+    pub fn tier(&self, index: usize) -> Option<&ModelTier> {
+        self.tiers.get(index)
+    }
+
+    pub fn len(&self) -> usize {
+        self.tiers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tiers.is_empty()
+    }
+}
@@ -1,13 +1,31 @@
 use crate::hhtc::HHTCEngine;
-use crate::ekf::EKFStorage;
-use crate::verifier::Verifier;
+use crate::ekf::{EKFStorage, RetrievedFact};
+use crate::verifier::{VerificationReport, Verifier};
 use crate::gpu_monitor::GPUMonitor;
-use crate::llm_integration::LLMClient;
-use crate::ffi;
+use crate::cascade::ModelCascade;
+use crate::generation::{GenerationParam, GenerationParams};
+use crate::endpoint_pool::{EndpointPool, EndpointStats};
+use crate::detectors::{self, LoopDetector, OutputLoop};
+use crate::explain::{ExplainReport, StageDecision};
+use crate::session::{now_secs, Session, SessionManager, SESSION_NAMESPACE};
+use crate::shadow::ShadowMode;
+use crate::health::{HealthReport, Protections, SubsystemStatus};
+use crate::pii::{PiiMap, PiiRedaction, PiiRedactor, PiiRestorer};
+use crate::policy::{PolicyDecision, PolicyStage, PolicyViolation};
+use crate::request::RequestOptions;
+use crate::resilience::HttpTransport;
+use crate::rollback::{AttemptRecord, RollbackConfig, RollbackPolicy, VerificationError};
+use crate::response_cache::{CacheKey, CachedResponse, ResponseCache};
+use crate::stats::{RequestSample, StatsSnapshot, StatsStore};
+use crate::streaming::{drive_stream, StreamEvent};
+use crate::usage::{estimate_tokens, CostReport, TokenUsage};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::path::Path;
 use std::sync::Arc;
-use tokio::sync::Mutex;
-use tracing::info;
+use std::time::Duration;
+use tokio::sync::{mpsc, Mutex};
+use tracing::{info, warn};
 use serde::{Serialize, Deserialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -18,6 +36,36 @@ pub struct ProcessedResponse {
     pub ekf_knowledge: Vec<String>,
     pub bandwidth_saved: f64,
     pub gpu_utilization: f64,
+    pub protections: Protections,
+    /// Stages skipped or interrupted because of the deadline or cancellation.
+    pub stages_cut: Vec<String>,
+    /// The output came from the response cache; no LLM call was made.
+    pub cache_hit: bool,
+    /// Cascade tier that produced the output; `None` on a cache hit.
+    pub answered_by: Option<String>,
+    /// How many times the request moved up to a stronger tier.
+    pub escalations: u32,
+    /// Tokens and money spent on every LLM call for this request, and what
+    /// they would have cost without OptimaCore's optimizations.
+    pub cost: CostReport,
+    /// Every generation made for this request, rejected ones included.
+    pub attempts: Vec<AttemptRecord>,
+    /// The attempt whose output passed verification; `None` when the output
+    /// was not verified or came from the cache.
+    pub passed_attempt: Option<u32>,
+    /// Per-claim verdicts for `output` against `ekf_knowledge`; `None` when
+    /// the output was not verified against any facts.
+    pub verification: Option<VerificationReport>,
+    /// The repetition loop `output` was truncated at, if any.
+    pub output_loop: Option<OutputLoop>,
+    /// `output` parsed as JSON, when the request carried a schema.
+    pub structured: Option<serde_json::Value>,
+    /// Content policy rules that fired on the prompt or the answer.
+    pub policy: Vec<PolicyDecision>,
+    /// PII replaced with placeholders before the prompt left OptimaCore.
+    /// `output` and `structured` have the values restored; `attempts` and
+    /// `verification` still show the placeholders.
+    pub pii: Vec<PiiRedaction>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -27,117 +75,1070 @@ pub struct OptimaStats {
     pub reflections_trimmed: u64,
     pub avg_gpu_utilization: f64,
     pub total_bandwidth_saved: f64,
+    #[serde(default)]
+    pub cache_hits: u64,
+    #[serde(default)]
+    pub escalations: u64,
+    #[serde(default)]
+    pub total_cost_usd: f64,
+    /// Estimated dollars saved by compression, trimming and caching.
+    #[serde(default)]
+    pub total_saved_usd: f64,
+    /// Requests answered by each cascade tier.
+    #[serde(default)]
+    pub answers_by_tier: BTreeMap<String, u64>,
+    #[serde(default)]
+    pub policy_blocks: u64,
+    #[serde(default)]
+    pub policy_redactions: u64,
+    #[serde(default)]
+    pub policy_flags: u64,
+    #[serde(default)]
+    pub policy_by_category: BTreeMap<String, u64>,
+    #[serde(default)]
+    pub pii_redactions: u64,
+    #[serde(default)]
+    pub pii_by_kind: BTreeMap<String, u64>,
+    #[serde(default)]
+    pub shadow_samples: u64,
+    /// Average word overlap of optimized and unoptimized answers.
+    #[serde(default)]
+    pub avg_shadow_similarity: f64,
+    /// Average verifier score of optimized minus unoptimized answers.
+    #[serde(default)]
+    pub avg_shadow_quality_delta: f64,
+    /// Average fraction of prompt tokens saved on shadowed requests.
+    #[serde(default)]
+    pub avg_shadow_token_savings: f64,
+    #[serde(default)]
+    pub shadow_cost_usd: f64,
+    /// Live per-endpoint load and health; not persisted with the counters.
+    #[serde(default)]
+    pub endpoint_stats: Vec<EndpointStats>,
+}
+
+/// The answer picked by `generate_verified`.
+struct GenerationOutcome {
+    output: String,
+    answered_by: String,
+    escalations: u32,
+    verified: bool,
+    cacheable: bool,
+    cost: CostReport,
+    attempts: Vec<AttemptRecord>,
+    passed_attempt: Option<u32>,
+    verification: Option<VerificationReport>,
+    output_loop: Option<OutputLoop>,
+    structured: Option<serde_json::Value>,
+}
+
+/// The next rollback policy to apply, starting at `cursor`. A
+/// `StrongerBackend` entry is reused while a stronger tier exists and
+/// skipped once none does.
+fn next_policy(policies: &[RollbackPolicy], cursor: &mut usize, stronger_tier_available: bool) -> Option<RollbackPolicy> {
+    while let Some(&policy) = policies.get(*cursor) {
+        if policy == RollbackPolicy::StrongerBackend {
+            if stronger_tier_available {
+                return Some(policy);
+            }
+            *cursor += 1;
+            continue;
+        }
+        *cursor += 1;
+        return Some(policy);
+    }
+    None
+}
+
+/// `prompt` followed by the output-shape instruction for the request's
+/// JSON Schema, if it has one.
+fn with_schema_instruction<'a>(prompt: &'a str, options: &RequestOptions) -> Cow<'a, str> {
+    match &options.schema {
+        Some(schema) => Cow::Owned(format!("{}\n\n{}", prompt, schema.instruction())),
+        None => Cow::Borrowed(prompt),
+    }
+}
+
+/// Intermediate state produced by the stages that run before the LLM call.
+struct PreparedPrompt {
+    compressed_prompt: String,
+    /// The trimmed prompt (with history) before HHTC compression.
+    uncompressed_prompt: String,
+    /// The prompt (with history) before trimming and compression.
+    unoptimized_prompt: String,
+    compression_ratio: f64,
+    reflection_detected: bool,
+    ekf_knowledge: Vec<String>,
+    /// `ekf_knowledge` with the EKF key of each fact.
+    ekf_facts: Vec<RetrievedFact>,
+    /// Estimated prompt tokens saved by trimming and compression.
+    saved_prompt_tokens: u64,
+    protections: Protections,
+    stages: Vec<StageDecision>,
+    stages_cut: Vec<String>,
 }
 
 pub struct OptimaCore {
-    hhtc: Arc<Mutex<HHTCEngine>>,
-    ekf: Arc<Mutex<EKFStorage>>,
+    hhtc: Option<Arc<Mutex<HHTCEngine>>>,
+    ekf: Option<Arc<Mutex<EKFStorage>>>,
     verifier: Arc<Mutex<Verifier>>,
-    gpu_monitor: Arc<Mutex<GPUMonitor>>,
-    llm_client: Arc<Mutex<LLMClient>>,
-    
-    request_count: u64,
-    total_compression: f64,
-    reflections_trimmed: u64,
-    total_bandwidth_saved: f64,
-    total_gpu_utilization: f64,
+    gpu_monitor: Option<Arc<Mutex<GPUMonitor>>>,
+    cascade: Arc<Mutex<ModelCascade>>,
+    /// Every tier's endpoints, with the transport holding their circuit breakers.
+    endpoint_pools: Vec<(Arc<EndpointPool>, Arc<HttpTransport>)>,
+    response_cache: Option<ResponseCache>,
+    sessions: SessionManager,
+    verification_reserve: Duration,
+    rollback: RollbackConfig,
+    /// `None` when `OPTIMA_LOOP_DETECTION=off`.
+    loop_detector: Option<LoopDetector>,
+    /// `None` when `OPTIMA_PII_REDACTION=off`.
+    pii: Option<PiiRedactor>,
+    shadow: Option<ShadowMode>,
+
+    hhtc_status: SubsystemStatus,
+    ekf_status: SubsystemStatus,
+    gpu_status: SubsystemStatus,
+
+    stats: Arc<std::sync::Mutex<StatsStore>>,
 }
 
 impl OptimaCore {
     pub async fn new(ekf_path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
-        let hhtc_engine = HHTCEngine::new(16, 1000).await?;
-        let ekf_storage = EKFStorage::new(ekf_path).await?;
-        let gpu_monitor = GPUMonitor::new().await?;
-        let llm_client = LLMClient::new().await?;
+        let (hhtc, hhtc_status) = match HHTCEngine::new(16, 1000).await {
+            Ok(engine) => (Some(Arc::new(Mutex::new(engine))), SubsystemStatus::Available),
+            Err(e) => {
+                warn!("HHTC engine unavailable, continuing without prompt compression: {}", e);
+                (None, SubsystemStatus::Disabled(e.to_string()))
+            }
+        };
+        let cascade = ModelCascade::from_env().await?;
+        let endpoint_pools = (0..cascade.len()).filter_map(|i| cascade.tier(i)).map(|tier| (tier.client.pool(), tier.client.transport())).collect();
+
+        let (ekf, ekf_status) = match EKFStorage::new(ekf_path).await {
+            Ok(storage) => (Some(Arc::new(Mutex::new(storage))), SubsystemStatus::Available),
+            Err(e) => {
+                warn!("EKF storage unavailable, continuing without knowledge retrieval: {}", e);
+                (None, SubsystemStatus::Disabled(e.to_string()))
+            }
+        };
+
+        let (gpu_monitor, gpu_status) = match GPUMonitor::new().await {
+            Ok(monitor) => (Some(Arc::new(Mutex::new(monitor))), SubsystemStatus::Available),
+            Err(e) => {
+                warn!("GPU monitoring unavailable, continuing without GPU metrics: {}", e);
+                (None, SubsystemStatus::Disabled(e.to_string()))
+            }
+        };
+
+        let response_cache = match ResponseCache::from_env() {
+            Ok(cache) => cache,
+            Err(e) => {
+                warn!("Response cache unavailable, every request goes to the LLM: {}", e);
+                None
+            }
+        };
+
+        #[cfg(feature = "julia")]
+        if !detectors::julia_status().is_available() {
+            warn!("Julia runtime unavailable: falling back to native reflection and contradiction detection.");
+        }
         
         Ok(Self {
-            hhtc: Arc::new(Mutex::new(hhtc_engine)),
-            ekf: Arc::new(Mutex::new(ekf_storage)),
-            verifier: Arc::new(Mutex::new(Verifier::new())),
-            gpu_monitor: Arc::new(Mutex::new(gpu_monitor)),
-            llm_client: Arc::new(Mutex::new(llm_client)),
-            request_count: 0,
-            total_compression: 0.0,
-            reflections_trimmed: 0,
-            total_bandwidth_saved: 0.0,
-            total_gpu_utilization: 0.0,
+            hhtc,
+            ekf,
+            verifier: Arc::new(Mutex::new(Verifier::new()?)),
+            gpu_monitor,
+            cascade: Arc::new(Mutex::new(cascade)),
+            endpoint_pools,
+            response_cache,
+            sessions: SessionManager::new(),
+            verification_reserve: Duration::from_millis(
+                std::env::var("OPTIMA_VERIFICATION_RESERVE_MS")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(250),
+            ),
+            rollback: RollbackConfig::from_env()?,
+            loop_detector: match std::env::var("OPTIMA_LOOP_DETECTION").as_deref() {
+                Ok("off") | Ok("false") | Ok("0") => None,
+                _ => Some(LoopDetector::from_env()),
+            },
+            pii: PiiRedactor::from_env()?,
+            shadow: ShadowMode::from_env(),
+            hhtc_status,
+            ekf_status,
+            gpu_status,
+            stats: Arc::new(std::sync::Mutex::new(StatsStore::new())),
         })
     }
 
-    pub async fn process_request(&mut self, prompt: &str) -> Result<ProcessedResponse, Box<dyn std::error::Error>> {
-        let reflection_detected = ffi::detect_reflection_loop(prompt).await;
-        let trimmed_prompt = if reflection_detected {
-            self.reflections_trimmed += 1;
-            info!("Reflection loop detected. Trimming prompt...");
-            let verifier = self.verifier.lock().await;
-            verifier.trim_reflection(prompt)
+    pub fn health(&self) -> HealthReport {
+        HealthReport {
+            hhtc: self.hhtc_status.clone(),
+            ekf: self.ekf_status.clone(),
+            julia_runtime: detectors::julia_status(),
+            gpu_monitor: self.gpu_status.clone(),
+            llm_client: self.llm_status(),
+        }
+    }
+
+    /// Degraded while some endpoint is unhealthy or has its circuit open,
+    /// disabled when none is left to send to.
+    fn llm_status(&self) -> SubsystemStatus {
+        let total: usize = self.endpoint_pools.iter().map(|(pool, _)| pool.len()).sum();
+        let available: usize = self.endpoint_pools.iter().map(|(pool, transport)| pool.available(transport)).sum();
+        if available == total {
+            SubsystemStatus::Available
+        } else if available == 0 {
+            SubsystemStatus::Disabled(format!("all {} LLM endpoint(s) unhealthy or circuit-open", total))
         } else {
-            prompt.to_string()
+            SubsystemStatus::Degraded(format!("{} of {} LLM endpoint(s) unhealthy or circuit-open", total - available, total))
+        }
+    }
+
+    pub async fn process_request(&mut self, prompt: &str) -> Result<ProcessedResponse, Box<dyn std::error::Error>> {
+        self.process_request_with(prompt, &RequestOptions::default()).await
+    }
+
+    /// Like `process_request`, bounded by the deadline and cancellation token
+    /// in `options`. Optional stages are skipped once time runs short; only
+    /// an interrupted LLM call fails the request.
+    pub async fn process_request_with(&mut self, prompt: &str, options: &RequestOptions) -> Result<ProcessedResponse, Box<dyn std::error::Error>> {
+        let (response, _) = self.run_pipeline(prompt, None, PiiMap::default(), options).await?;
+        Ok(response)
+    }
+
+    /// Stream the LLM response chunk by chunk. New sentences are verified
+    /// as they arrive; a detected contradiction ends the stream with
+    /// `StreamEvent::Aborted`. Dropping the receiver cancels generation.
+    /// Streams are always served by the first cascade tier; there is no
+    /// escalation once chunks have been sent.
+    /// Chunks cannot be withheld or redacted once sent, so streaming is
+    /// refused while the content policy blocks or redacts answers; flag-only
+    /// output rules run over the finished answer.
+    pub async fn process_request_stream(&mut self, prompt: &str, options: &RequestOptions) -> Result<mpsc::Receiver<StreamEvent>, Box<dyn std::error::Error>> {
+        if self.verifier.lock().await.policy_rewrites_output() {
+            return Err("streaming is unavailable while output policy rules block or redact answers".into());
+        }
+        let mut policy = Vec::new();
+        let prompt = self.enforce_input_policy(prompt, &mut policy).await?;
+        let (prompt, _, pii_map) = self.redact_pii(&prompt, None, PiiMap::default());
+        let pii = self.audit_pii(&pii_map);
+        let prompt = prompt.as_str();
+        let gpu_metrics = self.read_gpu_metrics().await;
+        let vram_bandwidth = gpu_metrics.map_or(0.0, |(_, bandwidth)| bandwidth);
+        let prepared = self.prepare(prompt, None, false, options).await;
+
+        let (tokens, answered_by, sent_prompt, price) = {
+            let cascade = self.cascade.lock().await;
+            let llm = cascade.primary();
+            let params = options.params.as_ref().unwrap_or(llm.default_params());
+            let tokens = options.run_stage(llm.generate_stream(&prepared.compressed_prompt, &prepared.ekf_knowledge, params)).await??;
+            let sent_prompt = llm.build_prompt(&prepared.compressed_prompt, &prepared.ekf_knowledge);
+            (tokens, cascade.primary_name().to_string(), sent_prompt, llm.price())
         };
 
-        let mut monitor = self.gpu_monitor.lock().await;
-        let gpu_utilization = monitor.get_utilization().await;
-        let vram_bandwidth = monitor.get_memory_bandwidth().await;
-        info!("GPU Utilization: {:.2}%, VRAM Bandwidth: {:.2} GB/s", gpu_utilization, vram_bandwidth);
-        
-        let (compressed_prompt, compression_ratio) = {
-            let mut hhtc = self.hhtc.lock().await;
-            hhtc.compress(&trimmed_prompt).await
+        let mut sample = RequestSample {
+            compression_ratio: prepared.compression_ratio,
+            reflection_trimmed: prepared.reflection_detected,
+            gpu_utilization: gpu_metrics.map(|(utilization, _)| utilization),
+            bandwidth_saved: vram_bandwidth * (1.0 - prepared.compression_ratio),
+            cache_hit: false,
+            escalations: 0,
+            answered_by: Some(answered_by),
+            policy,
+            pii,
+            ..RequestSample::default()
         };
-        info!("HHTC compression achieved: {:.2}% reduction", (1.0 - compression_ratio) * 100.0);
-        
-        let ekf_knowledge = {
-            let mut ekf = self.ekf.lock().await;
-            ekf.query(&compressed_prompt).await?
+
+        let verify_every = std::env::var("OPTIMA_STREAM_VERIFY_CHARS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(200);
+        let (tx, rx) = mpsc::channel(64);
+        let stream = drive_stream(
+            tokens,
+            self.verifier.clone(),
+            prepared.ekf_knowledge,
+            options.clone(),
+            verify_every,
+            PiiRestorer::new(pii_map.clone()),
+            tx.clone(),
+        );
+        // Backends report no usage mid-stream, so the sample is recorded
+        // once the stream ends, with tokens estimated from what was
+        // generated. Aborted and failed streams are billed too. The final
+        // event is only sent after that, so a caller that flushes stats when
+        // the stream closes does not lose the sample.
+        let stats = self.stats.clone();
+        let verifier = self.verifier.clone();
+        let saved_prompt_tokens = prepared.saved_prompt_tokens;
+        tokio::spawn(async move {
+            let (output, end) = stream.await;
+            let mut cost = CostReport::default();
+            cost.record_call(&TokenUsage::estimate(&sent_prompt, &output), saved_prompt_tokens, price);
+            sample.cost_usd = cost.cost_usd;
+            sample.saved_usd = cost.saved_usd();
+            if let Some(outcome) = verifier.lock().await.apply_policy(&pii_map.restore(&output), PolicyStage::Output) {
+                sample.policy.extend(outcome.decisions);
+            }
+            stats.lock().unwrap().record(&sample);
+            if let Some(end) = end {
+                let _ = tx.send(end).await;
+            }
+        });
+        Ok(rx)
+    }
+
+    /// Start a new conversation and return its id.
+    pub async fn create_session(&mut self) -> String {
+        self.expire_sessions().await;
+        let session = self.sessions.create().clone();
+        self.persist_session(&session).await;
+        session.id
+    }
+
+    /// Load a persisted session back into memory, e.g. after a restart.
+    /// Returns `Ok(false)` when no live session with this id exists.
+    pub async fn resume_session(&mut self, session_id: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        self.expire_sessions().await;
+        if self.sessions.get(session_id).is_some() {
+            return Ok(true);
+        }
+        let Some(ekf) = &self.ekf else {
+            return Ok(false);
         };
-        info!("EKF query returned {} knowledge snippets.", ekf_knowledge.len());
-        
-        let llm_output = {
-            let mut llm = self.llm_client.lock().await;
-            llm.generate(&compressed_prompt, &ekf_knowledge).await?
+
+        let stored: Option<Session> = {
+            let ekf = ekf.lock().await;
+            ekf.get_record(SESSION_NAMESPACE, session_id).await?
         };
-        
-        let final_output = {
-            let mut verifier = self.verifier.lock().await;
-            verifier.verify_and_rollback(&llm_output, &ekf_knowledge).await
+        let Some(session) = stored else {
+            return Ok(false);
+        };
+        if session.is_expired(self.sessions.idle_timeout(), now_secs()) {
+            info!("Session {} expired while persisted; discarding.", session_id);
+            let ekf = ekf.lock().await;
+            ekf.delete_record(SESSION_NAMESPACE, session_id).await?;
+            return Ok(false);
+        }
+
+        info!("Resumed session {} with {} turns.", session_id, session.turns.len());
+        self.sessions.insert(session);
+        Ok(true)
+    }
+
+    /// Process one turn of a conversation. Earlier turns are prepended to the
+    /// prompt so the model sees the whole exchange.
+    pub async fn process_turn(&mut self, session_id: &str, prompt: &str) -> Result<ProcessedResponse, Box<dyn std::error::Error>> {
+        self.process_turn_with(session_id, prompt, &RequestOptions::default()).await
+    }
+
+    pub async fn process_turn_with(&mut self, session_id: &str, prompt: &str, options: &RequestOptions) -> Result<ProcessedResponse, Box<dyn std::error::Error>> {
+        if !self.resume_session(session_id).await? {
+            return Err(format!("Unknown or expired session: {}", session_id).into());
+        }
+        let (history, session_pii) = match self.sessions.get(session_id) {
+            Some(session) => (session.transcript(), session.pii.clone()),
+            None => (String::new(), PiiMap::default()),
+        };
+        let history = if history.is_empty() { None } else { Some(history) };
+
+        let (response, pii_map) = self.run_pipeline(prompt, history.as_deref(), session_pii, options).await?;
+
+        // Turns are persisted, so they keep PII as placeholders; the values
+        // stay in memory with the session.
+        if let Some(session) = self.sessions.get_mut(session_id) {
+            session.record_turn(&pii_map.conceal(prompt), &pii_map.conceal(&response.output), response.compression_ratio, response.reflection_trimmed);
+            session.pii = pii_map;
+        }
+        if let Some(session) = self.sessions.get(session_id).cloned() {
+            self.persist_session(&session).await;
+        }
+        Ok(response)
+    }
+
+    pub fn session(&self, session_id: &str) -> Option<&Session> {
+        self.sessions.get(session_id)
+    }
+
+    pub async fn end_session(&mut self, session_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.sessions.remove(session_id);
+        if let Some(ekf) = &self.ekf {
+            let ekf = ekf.lock().await;
+            ekf.delete_record(SESSION_NAMESPACE, session_id).await?;
+        }
+        Ok(())
+    }
+
+    async fn persist_session(&self, session: &Session) {
+        if let Some(ekf) = &self.ekf {
+            let ekf = ekf.lock().await;
+            if let Err(e) = ekf.put_record(SESSION_NAMESPACE, &session.id, session).await {
+                warn!("Failed to persist session {}: {}", session.id, e);
+            }
+        }
+    }
+
+    async fn expire_sessions(&mut self) {
+        let expired = self.sessions.expire_idle();
+        if let Some(ekf) = &self.ekf {
+            let ekf = ekf.lock().await;
+            for id in expired {
+                if let Err(e) = ekf.delete_record(SESSION_NAMESPACE, &id).await {
+                    warn!("Failed to delete expired session {}: {}", id, e);
+                }
+            }
+        }
+    }
+
+    /// Apply the content policy to a prompt. A blocked prompt is counted
+    /// in the stats and fails the request; otherwise the prompt comes back
+    /// with any redactions applied.
+    async fn enforce_input_policy(&mut self, prompt: &str, decisions: &mut Vec<PolicyDecision>) -> Result<String, PolicyViolation> {
+        let Some(outcome) = self.verifier.lock().await.apply_policy(prompt, PolicyStage::Input) else {
+            return Ok(prompt.to_string());
+        };
+        decisions.extend(outcome.decisions.iter().cloned());
+        if outcome.blocked() {
+            let violation = PolicyViolation { decisions: decisions.clone() };
+            warn!("Request rejected: {}", violation);
+            self.record_sample(&RequestSample { compression_ratio: 1.0, policy: decisions.clone(), ..RequestSample::default() });
+            return Err(violation);
+        }
+        Ok(outcome.text)
+    }
+
+    /// Swap PII in the prompt and history for placeholders, extending `map`
+    /// (a session's placeholders so far), so a value keeps its placeholder
+    /// across turns.
+    fn redact_pii(&self, prompt: &str, history: Option<&str>, mut map: PiiMap) -> (String, Option<String>, PiiMap) {
+        let Some(redactor) = &self.pii else {
+            return (prompt.to_string(), history.map(str::to_string), map);
         };
+        let history = history.map(|history| redactor.redact(history, &mut map));
+        let prompt = redactor.redact(prompt, &mut map);
+        (prompt, history, map)
+    }
+
+    fn audit_pii(&self, map: &PiiMap) -> Vec<PiiRedaction> {
+        match &self.pii {
+            Some(redactor) if !map.is_empty() => {
+                let redactions = redactor.audit(map);
+                info!("Redacted {} PII value(s) from the prompt", redactions.len());
+                redactions
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Returns the response and the PII placeholders used, `pii_map`
+    /// extended with any new ones.
+    async fn run_pipeline(
+        &mut self,
+        prompt: &str,
+        history: Option<&str>,
+        pii_map: PiiMap,
+        options: &RequestOptions,
+    ) -> Result<(ProcessedResponse, PiiMap), Box<dyn std::error::Error>> {
+        let mut policy = Vec::new();
+        let prompt = self.enforce_input_policy(prompt, &mut policy).await?;
+        let (prompt, history, pii_map) = self.redact_pii(&prompt, history, pii_map);
+        let pii = self.audit_pii(&pii_map);
+        let (prompt, history) = (prompt.as_str(), history.as_deref());
+        let (gpu_utilization, vram_bandwidth, gpu_metrics) = match self.read_gpu_metrics().await {
+            Some((utilization, bandwidth)) => (utilization, bandwidth, true),
+            None => (0.0, 0.0, false),
+        };
+        info!("GPU Utilization: {:.2}%, VRAM Bandwidth: {:.2} GB/s", gpu_utilization, vram_bandwidth);
+
+        let prepared = self.prepare(prompt, history, false, options).await;
+        let mut protections = prepared.protections.clone();
+        protections.gpu_metrics = gpu_metrics;
+        let mut stages_cut = prepared.stages_cut.clone();
         
+        let (cache_key, params, final_prompt, primary_price) = {
+            let cascade = self.cascade.lock().await;
+            let llm = cascade.primary();
+            let params = options.params.clone().unwrap_or_else(|| llm.default_params().clone());
+            let prompt = with_schema_instruction(&prepared.compressed_prompt, options);
+            let final_prompt = llm.build_prompt(&prompt, &prepared.ekf_knowledge);
+            // Keyed on the prompt before HHTC compression, whose output
+            // depends on what the engine has cached so far.
+            let key_prompt = with_schema_instruction(&prepared.uncompressed_prompt, options);
+            let key_final_prompt = llm.build_prompt(&key_prompt, &prepared.ekf_knowledge);
+            let key = CacheKey::new(llm.backend().name(), &key_final_prompt, &prepared.ekf_knowledge, &params);
+            (key, params, final_prompt, llm.price())
+        };
+        let cached = self.cache_lookup(&cache_key).await;
+        let cache_hit = cached.is_some();
+
+        let (output, answered_by, escalations, cost, attempts, passed_attempt, verification, output_loop, mut structured) = if let Some(cached) = cached {
+            info!("Response cache hit; skipping the LLM call.");
+            protections.verification = cached.verified;
+            let mut cost = CostReport::default();
+            let mut avoided = TokenUsage::estimate(&final_prompt, &cached.output);
+            avoided.prompt_tokens += prepared.saved_prompt_tokens;
+            cost.record_avoided_call(&avoided, primary_price);
+            let structured = options.schema.as_ref().and_then(|schema| schema.check(&cached.output).ok()).map(|checked| checked.value);
+            (cached.output, None, 0, cost, Vec::new(), None, None, None, structured)
+        } else {
+            let outcome = self.generate_verified(&prepared, &params, options, &mut stages_cut).await?;
+            protections.verification = outcome.verified;
+            if let Some(shadow) = self.shadow.filter(ShadowMode::sample) {
+                self.spawn_shadow(shadow, &prepared, &params, options, &outcome.output).await;
+            }
+            if outcome.cacheable {
+                if let Some(cache) = &self.response_cache {
+                    if let Err(e) = cache.put(&cache_key, &outcome.output, outcome.verified) {
+                        warn!("Failed to store response in cache: {}", e);
+                    }
+                }
+            }
+            (
+                outcome.output,
+                Some(outcome.answered_by),
+                outcome.escalations,
+                outcome.cost,
+                outcome.attempts,
+                outcome.passed_attempt,
+                outcome.verification,
+                outcome.output_loop,
+                outcome.structured,
+            )
+        };
+
+        // The cache holds the placeholder form, so restore after it.
+        let output = pii_map.restore(&output);
+        if let Some(value) = structured.as_mut() {
+            pii_map.restore_value(value);
+        }
+
+        let (output, output_blocked) = match self.verifier.lock().await.apply_policy(&output, PolicyStage::Output) {
+            Some(outcome) => {
+                let blocked = outcome.blocked();
+                policy.extend(outcome.decisions);
+                (outcome.text, blocked)
+            }
+            None => (output, false),
+        };
+
+        let compression_ratio = prepared.compression_ratio;
         let bandwidth_saved = vram_bandwidth * (1.0 - compression_ratio);
         
-        self.request_count += 1;
-        self.total_compression += 1.0 - compression_ratio;
-        self.total_bandwidth_saved += bandwidth_saved;
-        self.total_gpu_utilization += gpu_utilization;
+        self.record_sample(&RequestSample {
+            compression_ratio,
+            reflection_trimmed: prepared.reflection_detected,
+            gpu_utilization: gpu_metrics.then_some(gpu_utilization),
+            bandwidth_saved,
+            cache_hit,
+            escalations,
+            answered_by: answered_by.clone(),
+            cost_usd: cost.cost_usd,
+            saved_usd: cost.saved_usd(),
+            policy: policy.clone(),
+            pii: pii.clone(),
+        });
+        if output_blocked {
+            let violation = PolicyViolation { decisions: policy };
+            warn!("Answer withheld: {}", violation);
+            return Err(Box::new(violation));
+        }
         
-        Ok(ProcessedResponse {
-            output: final_output,
+        let response = ProcessedResponse {
+            output,
             compression_ratio,
-            reflection_trimmed: reflection_detected,
-            ekf_knowledge,
+            reflection_trimmed: prepared.reflection_detected,
+            ekf_knowledge: prepared.ekf_knowledge,
             bandwidth_saved,
             gpu_utilization,
-        })
+            protections,
+            stages_cut,
+            cache_hit,
+            answered_by,
+            escalations,
+            cost,
+            attempts,
+            passed_attempt,
+            verification,
+            output_loop,
+            structured,
+            policy,
+            pii,
+        };
+        Ok((response, pii_map))
     }
-    
-    pub fn get_stats(&self) -> OptimaStats {
-        let avg_compression_ratio = if self.request_count > 0 {
-            self.total_compression / self.request_count as f64
+
+    /// Send the request again without reflection trimming or HHTC
+    /// compression, keeping the EKF knowledge so only those two differ, and
+    /// record how the answers compare once the baseline arrives. Runs after
+    /// the response is returned, bounded only by the transport timeouts.
+    /// Streams and cache hits are never shadowed. A failed shadow call only
+    /// loses the sample.
+    async fn spawn_shadow(&self, shadow: ShadowMode, prepared: &PreparedPrompt, params: &GenerationParams, options: &RequestOptions, output: &str) {
+        let prompt = with_schema_instruction(&prepared.unoptimized_prompt, options).into_owned();
+        let llm = self.cascade.lock().await.shared_primary();
+        let knowledge = prepared.ekf_knowledge.clone();
+        let saved_prompt_tokens = prepared.saved_prompt_tokens;
+        let params = params.clone();
+        let output = output.to_string();
+        let verifier = self.verifier.clone();
+        let stats = self.stats.clone();
+        tokio::spawn(async move {
+            let baseline = match llm.generate(&prompt, &knowledge, &params).await {
+                Ok(baseline) => baseline,
+                Err(e) => {
+                    warn!("Shadow request failed: {}", e);
+                    return;
+                }
+            };
+            let comparison = shadow.compare(&*verifier.lock().await, &knowledge, &output, baseline, saved_prompt_tokens, llm.price()).await;
+            info!(
+                "Shadow comparison: similarity {:.2}, quality delta {:+.2}, {:.2}% prompt tokens saved",
+                comparison.similarity,
+                comparison.quality_delta,
+                comparison.token_savings() * 100.0
+            );
+            stats.lock().unwrap().record_shadow(&comparison);
+        });
+    }
+
+    /// Generate, verify, and on rejection retry under the configured
+    /// rollback policies until an answer passes or the attempts run out.
+    /// Escalating to a stronger cascade tier is itself one of the policies.
+    async fn generate_verified(
+        &self,
+        prepared: &PreparedPrompt,
+        params: &GenerationParams,
+        options: &RequestOptions,
+        stages_cut: &mut Vec<String>,
+    ) -> Result<GenerationOutcome, Box<dyn std::error::Error>> {
+        let mut prompt = prepared.compressed_prompt.as_str();
+        let mut extra_baseline_tokens = prepared.saved_prompt_tokens;
+        let mut knowledge = prepared.ekf_knowledge.clone();
+        let mut params = params.clone();
+        let mut tier_index = 0;
+        let mut policy_cursor = 0;
+        let mut policy = None;
+        let mut escalations = 0;
+        let mut attempts: Vec<AttemptRecord> = Vec::new();
+        let mut cost = CostReport::default();
+        let mut schema_note: Option<String> = None;
+        let mut schema_retries = 0;
+        loop {
+            let mut request_prompt = with_schema_instruction(prompt, options).into_owned();
+            if let Some(note) = &schema_note {
+                request_prompt.push_str("\n\n");
+                request_prompt.push_str(note);
+            }
+            let (answered_by, mut llm_output, tier_count, takes_penalty) = {
+                let cascade = self.cascade.lock().await;
+                let Some(tier) = cascade.tier(tier_index) else {
+                    return Err("model cascade has no tiers".into());
+                };
+                let (llm_output, usage) = options.run_stage(tier.client.generate(&request_prompt, &knowledge, &params)).await??;
+                cost.record_call(&usage, extra_baseline_tokens, tier.client.price());
+                let takes_penalty = tier.client.backend().supported_params().contains(&GenerationParam::FrequencyPenalty);
+                (tier.name.clone(), llm_output, cascade.len(), takes_penalty)
+            };
+            attempts.push(AttemptRecord { attempt: attempts.len() as u32, tier: answered_by.clone(), policy, rejected: None });
+
+            let output_loop = self.loop_detector.as_ref().and_then(|detector| detector.detect(&llm_output));
+            if let Some(found) = &output_loop {
+                warn!("Attempt {} on tier '{}' loops ({}); truncating at byte {}.", attempts.len() - 1, answered_by, found.reason, found.start);
+                llm_output.truncate(found.start);
+                llm_output.truncate(llm_output.trim_end().len());
+
+                let retries = attempts.len() as u32 - 1;
+                let penalty = self.rollback.repetition_penalty.filter(|penalty| params.frequency_penalty.is_none_or(|current| current < *penalty));
+                if let Some(penalty) = penalty.filter(|_| takes_penalty && retries < self.rollback.max_attempts && options.check().is_ok()) {
+                    info!("Regenerating with frequency penalty {}.", penalty);
+                    if let Some(attempt) = attempts.last_mut() {
+                        attempt.rejected = Some(format!("output loop: {}", found.reason));
+                    }
+                    params.frequency_penalty = Some(penalty);
+                    policy = Some(RollbackPolicy::RepetitionPenalty);
+                    continue;
+                }
+            }
+
+            let mut structured = None;
+            if let Some(schema) = &options.schema {
+                let checked = self.verifier.lock().await.check_structure(&llm_output, schema);
+                match checked {
+                    Ok(checked) => {
+                        if checked.repaired {
+                            info!("Repaired malformed JSON from attempt {} locally.", attempts.len() - 1);
+                            llm_output = checked.text;
+                        }
+                        structured = Some(checked.value);
+                    }
+                    Err(errors) => {
+                        warn!("Attempt {} on tier '{}' does not match the schema: {}.", attempts.len() - 1, answered_by, errors.join("; "));
+                        if let Some(attempt) = attempts.last_mut() {
+                            attempt.rejected = Some(format!("schema: {}", errors.join("; ")));
+                        }
+                        if schema_retries < self.rollback.schema_retries && options.check().is_ok() {
+                            schema_retries += 1;
+                            schema_note = Some(format!(
+                                "Your previous reply did not match the schema:\n- {}\nReply again with corrected JSON only.",
+                                errors.join("\n- ")
+                            ));
+                            policy = Some(RollbackPolicy::SchemaRepair);
+                            continue;
+                        }
+                        return Err(Box::new(VerificationError { attempts, last_output: llm_output, report: None }));
+                    }
+                }
+            }
+
+            let unverified = |output: String, answered_by: String, attempts: Vec<AttemptRecord>| GenerationOutcome {
+                output,
+                answered_by,
+                escalations,
+                verified: false,
+                cacheable: false,
+                cost,
+                attempts,
+                passed_attempt: None,
+                verification: None,
+                output_loop: output_loop.clone(),
+                structured: structured.clone(),
+            };
+            if !options.has_time_for(self.verification_reserve) {
+                warn!("Skipping verification: not enough time left before the deadline.");
+                stages_cut.push("verification".to_string());
+                return Ok(unverified(llm_output, answered_by, attempts));
+            }
+
+            let verifier = self.verifier.lock().await;
+            // Always verify against the retrieved facts, not the rollback notes.
+            let assessment = match options.run_stage(verifier.assess(&llm_output, &prepared.ekf_knowledge)).await {
+                Ok(assessment) => assessment,
+                Err(interrupted) => {
+                    warn!("Verification interrupted ({}); returning unverified output.", interrupted);
+                    stages_cut.push("verification".to_string());
+                    return Ok(unverified(llm_output, answered_by, attempts));
+                }
+            };
+            let report = if prepared.ekf_facts.is_empty() {
+                None
+            } else {
+                Some(verifier.report(&llm_output, &prepared.ekf_facts).await)
+            };
+
+            let Some(reason) = assessment.failure() else {
+                let passed_attempt = attempts.last().map(|attempt| attempt.attempt);
+                if passed_attempt.is_some_and(|attempt| attempt > 0) {
+                    info!("Rollback attempt {} passed verification on tier '{}'.", attempts.len() - 1, answered_by);
+                }
+                return Ok(GenerationOutcome {
+                    output: llm_output,
+                    answered_by,
+                    escalations,
+                    verified: assessment.contradiction_score.is_some(),
+                    cacheable: true,
+                    cost,
+                    attempts,
+                    passed_attempt,
+                    verification: report,
+                    output_loop,
+                    structured,
+                });
+            };
+            warn!("Attempt {} on tier '{}' rejected: {}.", attempts.len() - 1, answered_by, reason);
+            if let Some(attempt) = attempts.last_mut() {
+                attempt.rejected = Some(reason);
+            }
+
+            let retries = attempts.len() as u32 - 1;
+            let next = if retries < self.rollback.max_attempts && options.check().is_ok() {
+                next_policy(&self.rollback.policies, &mut policy_cursor, tier_index + 1 < tier_count)
+            } else {
+                None
+            };
+            let Some(next) = next else {
+                if options.check().is_err() {
+                    stages_cut.push("rollback".to_string());
+                }
+                return Err(Box::new(VerificationError { attempts, last_output: llm_output, report }));
+            };
+
+            info!("Rolling back with policy {:?}.", next);
+            match next {
+                RollbackPolicy::StrongerBackend => {
+                    tier_index += 1;
+                    escalations += 1;
+                }
+                RollbackPolicy::ExplicitFacts => {
+                    let offending = verifier.offending_facts(&llm_output, &prepared.ekf_knowledge).await;
+                    knowledge.extend(offending.into_iter().map(|fact| {
+                        format!("A previous answer contradicted this fact; your answer must be consistent with it: {}", fact)
+                    }));
+                }
+                RollbackPolicy::LowerTemperature => {
+                    params.temperature = params.temperature.min(self.rollback.temperature);
+                }
+                RollbackPolicy::RepetitionPenalty => {
+                    if let Some(penalty) = self.rollback.repetition_penalty.filter(|_| takes_penalty) {
+                        params.frequency_penalty = Some(penalty);
+                    }
+                }
+                // Only applied on schema failures above; `next_policy` never
+                // returns it.
+                RollbackPolicy::SchemaRepair => {}
+                RollbackPolicy::NoCompression => {
+                    prompt = prepared.uncompressed_prompt.as_str();
+                    let compression_savings = estimate_tokens(prompt).saturating_sub(estimate_tokens(&prepared.compressed_prompt));
+                    extra_baseline_tokens = prepared.saved_prompt_tokens.saturating_sub(compression_savings);
+                }
+            }
+            policy = Some(next);
+        }
+    }
+
+    async fn cache_lookup(&self, key: &CacheKey) -> Option<CachedResponse> {
+        let cache = self.response_cache.as_ref()?;
+        match cache.get(key) {
+            Ok(hit) => hit,
+            Err(e) => {
+                warn!("Response cache lookup failed: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Run reflection detection, trimming, HHTC and EKF retrieval without
+    /// calling the LLM, and report what each stage decided. The HHTC cache
+    /// and request statistics are left untouched.
+    pub async fn explain(&mut self, prompt: &str) -> Result<ExplainReport, Box<dyn std::error::Error>> {
+        let (redacted, _, pii_map) = self.redact_pii(prompt, None, PiiMap::default());
+        let prepared = self.prepare(&redacted, None, true, &RequestOptions::default()).await;
+        let (final_prompt, final_messages, generation_params, cache_key) = {
+            let cascade = self.cascade.lock().await;
+            let llm = cascade.primary();
+            let final_prompt = llm.build_prompt(&prepared.compressed_prompt, &prepared.ekf_knowledge);
+            let params = llm.default_params().clone();
+            let key_final_prompt = llm.build_prompt(&prepared.uncompressed_prompt, &prepared.ekf_knowledge);
+            let key = CacheKey::new(llm.backend().name(), &key_final_prompt, &prepared.ekf_knowledge, &params);
+            (final_prompt, llm.build_messages(&prepared.compressed_prompt, &prepared.ekf_knowledge), params, key)
+        };
+
+        let mut stages = vec![match (&self.pii, pii_map.is_empty()) {
+            (None, _) => StageDecision::new("pii_redaction", false, "Skipped: PII redaction disabled"),
+            (Some(_), true) => StageDecision::new("pii_redaction", false, "No PII found in prompt"),
+            (Some(_), false) => StageDecision::new("pii_redaction", true, "PII replaced with placeholders before compression"),
+        }];
+        stages.extend(prepared.stages);
+        stages.push(match (&self.response_cache, self.cache_lookup(&cache_key).await) {
+            (None, _) => StageDecision::new("response_cache", false, "Skipped: response cache disabled"),
+            (Some(_), Some(_)) => StageDecision::new("response_cache", true, "Would answer from the response cache"),
+            (Some(_), None) => StageDecision::new("response_cache", false, "No cached response for this prompt"),
+        });
+        stages.push(StageDecision::new("llm_generation", false, "Skipped: dry run"));
+        let verification_reason = if prepared.ekf_knowledge.is_empty() {
+            "Skipped: no EKF knowledge to verify against"
         } else {
-            0.0
+            "Would check the LLM output against the retrieved knowledge"
         };
-        
-        let avg_gpu_utilization = if self.request_count > 0 {
-            self.total_gpu_utilization / self.request_count as f64
+        stages.push(StageDecision::new("verification", false, verification_reason));
+
+        Ok(ExplainReport {
+            original_prompt: prompt.to_string(),
+            final_prompt,
+            final_messages,
+            generation_params,
+            compression_ratio: prepared.compression_ratio,
+            ekf_knowledge: prepared.ekf_knowledge,
+            stages,
+        })
+    }
+
+    async fn prepare(&mut self, prompt: &str, history: Option<&str>, dry_run: bool, options: &RequestOptions) -> PreparedPrompt {
+        let mut protections = Protections::default();
+        let mut stages = Vec::new();
+        let mut stages_cut = Vec::new();
+
+        let reflection_detected = match options.run_stage(detectors::detect_reflection_loop(prompt)).await {
+            Err(interrupted) => {
+                stages_cut.push("reflection_detection".to_string());
+                stages.push(StageDecision::new("reflection_detection", false, format!("Skipped: {}", interrupted)));
+                false
+            }
+            Ok(detected) => {
+                protections.reflection_detection = true;
+                let reason = if detected {
+                    "Reflection phrases or keywords found in prompt"
+                } else {
+                    "No reflection loop found"
+                };
+                stages.push(StageDecision::new("reflection_detection", detected, reason));
+                detected
+            }
+        };
+        let trimmed_prompt = if reflection_detected {
+            info!("Reflection loop detected. Trimming prompt...");
+            let verifier = self.verifier.lock().await;
+            let trimmed = verifier.trim_reflection(prompt);
+            let reason = format!(
+                "Trimmed at first reflection indicator ({} -> {} words)",
+                prompt.split_whitespace().count(),
+                trimmed.split_whitespace().count()
+            );
+            stages.push(StageDecision::new("reflection_trimming", true, reason));
+            trimmed
         } else {
-            0.0
+            stages.push(StageDecision::new("reflection_trimming", false, "No reflection loop detected"));
+            prompt.to_string()
+        };
+        let full_prompt = match history {
+            Some(history) => format!("{}\nUser: {}", history, trimmed_prompt),
+            None => trimmed_prompt.clone(),
+        };
+
+        // Only the new prompt is compressed. Earlier turns go out verbatim:
+        // a surrogate is opaque to the model, and the history has to stay
+        // readable for the conversation to make sense.
+        let compressed = match &self.hhtc {
+            Some(hhtc) => {
+                let mut hhtc = hhtc.lock().await;
+                let compressed = options
+                    .run_stage(async {
+                        if dry_run {
+                            hhtc.preview(&trimmed_prompt).await
+                        } else {
+                            hhtc.compress(&trimmed_prompt).await
+                        }
+                    })
+                    .await;
+                Some(compressed)
+            }
+            None => None,
+        };
+        let (compressed_prompt, compression_ratio) = match compressed {
+            Some(Ok((compressed_prompt, compression_ratio))) => {
+                protections.hhtc_compression = true;
+                info!("HHTC compression achieved: {:.2}% reduction", (1.0 - compression_ratio) * 100.0);
+                let hhtc_reason = if compression_ratio < 1.0 {
+                    format!("Replaced cached chunks with surrogate tokens ({:.2}% reduction)", (1.0 - compression_ratio) * 100.0)
+                } else {
+                    "No cached chunks matched".to_string()
+                };
+                stages.push(StageDecision::new("hhtc_compression", compression_ratio < 1.0, hhtc_reason));
+                (compressed_prompt, compression_ratio)
+            }
+            Some(Err(interrupted)) => {
+                stages_cut.push("hhtc_compression".to_string());
+                stages.push(StageDecision::new("hhtc_compression", false, format!("Skipped: {}", interrupted)));
+                (trimmed_prompt.clone(), 1.0)
+            }
+            None => {
+                stages.push(StageDecision::new("hhtc_compression", false, "Skipped: HHTC engine unavailable"));
+                (trimmed_prompt.clone(), 1.0)
+            }
+        };
+        let (compressed_prompt, compression_ratio) = match history {
+            Some(history) => {
+                let history_words = (history.split_whitespace().count() + 1) as f64;
+                let prompt_words = trimmed_prompt.split_whitespace().count() as f64;
+                let ratio = (history_words + compression_ratio * prompt_words) / (history_words + prompt_words);
+                (format!("{}\nUser: {}", history, compressed_prompt), ratio)
+            }
+            None => (compressed_prompt, compression_ratio),
         };
         
-        OptimaStats {
-            total_requests: self.request_count,
-            avg_compression_ratio,
-            reflections_trimmed: self.reflections_trimmed,
-            avg_gpu_utilization,
-            total_bandwidth_saved: self.total_bandwidth_saved,
+        let ekf_facts = match &self.ekf {
+            Some(ekf) => {
+                let ekf = ekf.lock().await;
+                match options.run_stage(ekf.retrieve(&compressed_prompt)).await {
+                    Err(interrupted) => {
+                        stages_cut.push("ekf_retrieval".to_string());
+                        stages.push(StageDecision::new("ekf_retrieval", false, format!("Skipped: {}", interrupted)));
+                        Vec::new()
+                    }
+                    Ok(Ok(facts)) => {
+                        protections.ekf_retrieval = true;
+                        let reason = format!("{} snippets above the similarity threshold", facts.len());
+                        stages.push(StageDecision::new("ekf_retrieval", !facts.is_empty(), reason));
+                        facts
+                    }
+                    Ok(Err(e)) => {
+                        warn!("EKF query failed, continuing without knowledge: {}", e);
+                        self.ekf_status = SubsystemStatus::Degraded(e.to_string());
+                        stages.push(StageDecision::new("ekf_retrieval", false, format!("Skipped: query failed ({})", e)));
+                        Vec::new()
+                    }
+                }
+            }
+            None => {
+                stages.push(StageDecision::new("ekf_retrieval", false, "Skipped: EKF storage unavailable"));
+                Vec::new()
+            }
+        };
+        let ekf_knowledge: Vec<String> = ekf_facts.iter().map(|fact| fact.value.clone()).collect();
+        info!("EKF query returned {} knowledge snippets.", ekf_knowledge.len());
+
+        let unoptimized_prompt = match history {
+            Some(history) => format!("{}\nUser: {}", history, prompt),
+            None => prompt.to_string(),
+        };
+        let saved_prompt_tokens = estimate_tokens(&unoptimized_prompt).saturating_sub(estimate_tokens(&compressed_prompt));
+
+        PreparedPrompt {
+            compressed_prompt,
+            uncompressed_prompt: full_prompt,
+            unoptimized_prompt,
+            compression_ratio,
+            reflection_detected,
+            ekf_knowledge,
+            ekf_facts,
+            saved_prompt_tokens,
+            protections,
+            stages,
+            stages_cut,
+        }
+    }
+
+    async fn read_gpu_metrics(&mut self) -> Option<(f64, f64)> {
+        let monitor = self.gpu_monitor.as_ref()?;
+        let mut monitor = monitor.lock().await;
+        let metrics = match monitor.get_utilization().await {
+            Ok(utilization) => monitor
+                .get_memory_bandwidth()
+                .await
+                .map(|bandwidth| (utilization, bandwidth)),
+            Err(e) => Err(e),
+        };
+        match metrics {
+            Ok(metrics) => {
+                self.gpu_status = SubsystemStatus::Available;
+                Some(metrics)
+            }
+            Err(e) => {
+                warn!("Failed to read GPU metrics: {}", e);
+                self.gpu_status = SubsystemStatus::Degraded(e.to_string());
+                None
+            }
         }
     }
-}
\ No newline at end of file
+    
+    pub fn get_stats(&self) -> OptimaStats {
+        let mut stats = self.stats.lock().unwrap().snapshot().totals.summary();
+        stats.endpoint_stats = self.endpoint_pools.iter().flat_map(|(pool, _)| pool.stats()).collect();
+        stats
+    }
+
+    /// Totals plus hourly and daily rollups for this worker.
+    pub fn stats_snapshot(&self) -> StatsSnapshot {
+        self.stats.lock().unwrap().snapshot().clone()
+    }
+
+    pub fn flush_stats(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.stats.lock().unwrap().flush()
+    }
+
+    fn record_sample(&self, sample: &RequestSample) {
+        self.stats.lock().unwrap().record(sample);
+    }
+}
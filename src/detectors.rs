@@ -0,0 +1,284 @@
+use flate2::{write::DeflateEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+
+use crate::health::SubsystemStatus;
+use crate::rules::RuleSet;
+use crate::verifier::split_claims;
+
+const REFLECTION_PHRASES: &[&str] = &[
+    "think about it",
+    "actually, think again",
+    "consider a different approach",
+    "reconsider your answer",
+    "reflect on this",
+    "go back and think",
+    "let me re-evaluate",
+];
+const REFLECTION_KEYWORDS: &[&str] = &["think", "reconsider", "reflect", "again", "evaluate"];
+/// Self-correction phrases that, repeated in an output, mean the model is
+/// going round in circles.
+const OUTPUT_LOOP_PHRASES: &[&str] = &[
+    "let me think again",
+    "let me reconsider",
+    "let me re-evaluate",
+    "wait, let me",
+    "on second thought",
+    "actually, think again",
+    "go back and think",
+];
+/// Words per n-gram for the repetition ratio.
+const LOOP_NGRAM: usize = 4;
+/// Outputs shorter than this are too small for a meaningful compression
+/// ratio.
+const LOOP_MIN_COMPRESSIBLE_BYTES: usize = 200;
+
+/// Whether `prompt` asks the model to second-guess itself: a known
+/// reflection phrase, or several reflection keywords (two in a short
+/// prompt, three in one of 100 characters or more).
+pub fn reflection_loop_native(prompt: &str) -> bool {
+    let lower_prompt = prompt.to_lowercase();
+    if REFLECTION_PHRASES.iter().any(|phrase| lower_prompt.contains(phrase)) {
+        return true;
+    }
+    let keyword_count = REFLECTION_KEYWORDS.iter().filter(|keyword| lower_prompt.contains(*keyword)).count();
+    if prompt.chars().count() < 100 {
+        keyword_count >= 2
+    } else {
+        keyword_count >= 3
+    }
+}
+
+/// Runs in the Julia runtime when built with the `julia` feature and the
+/// runtime is initialized; natively otherwise.
+pub async fn detect_reflection_loop(prompt: &str) -> bool {
+    #[cfg(feature = "julia")]
+    if let Some(detected) = crate::ffi::detect_reflection_loop(prompt).await {
+        return detected;
+    }
+    reflection_loop_native(prompt)
+}
+
+/// Scores `output` against `rules`. The Julia runtime only stands in for
+/// the built-in pack, since it cannot load rule packs.
+pub async fn check_for_contradiction(output: &str, facts: &[String], rules: &RuleSet) -> f64 {
+    #[cfg(feature = "julia")]
+    if rules.is_builtin() {
+        if let Some(score) = crate::ffi::check_for_contradiction(output, facts).await {
+            return score;
+        }
+    }
+    rules.score(output, facts)
+}
+
+/// Status of the optional Julia backend for the heuristics.
+pub fn julia_status() -> SubsystemStatus {
+    #[cfg(feature = "julia")]
+    return crate::ffi::julia_status();
+    #[cfg(not(feature = "julia"))]
+    SubsystemStatus::Disabled("built without the `julia` feature; using native detectors".to_string())
+}
+
+/// A degenerate loop in LLM output.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutputLoop {
+    /// Byte offset where the repetition starts; the output is cut here,
+    /// keeping the first occurrence.
+    pub start: usize,
+    /// Share of word 4-grams that repeat an earlier one.
+    pub ngram_repetition: f64,
+    /// Deflate-compressed size over raw size; repetitive text compresses
+    /// well. 1 for outputs too short to judge.
+    pub compression_ratio: f64,
+    pub reason: String,
+}
+
+/// Finds repeated sentences, repeated self-correction phrases and
+/// low-entropy token loops in LLM output.
+#[derive(Debug, Clone)]
+pub struct LoopDetector {
+    max_ngram_repetition: f64,
+    min_compression_ratio: f64,
+    sentence_repeats: usize,
+}
+
+impl LoopDetector {
+    /// Thresholds from `OPTIMA_LOOP_NGRAM_RATIO` (default 0.5),
+    /// `OPTIMA_LOOP_COMPRESSION_RATIO` (default 0.3; both must be crossed)
+    /// and `OPTIMA_LOOP_SENTENCE_REPEATS` (default 3).
+    pub fn from_env() -> Self {
+        let env = |name: &str| std::env::var(name).ok().and_then(|v| v.parse::<f64>().ok());
+        Self {
+            max_ngram_repetition: env("OPTIMA_LOOP_NGRAM_RATIO").unwrap_or(0.5),
+            min_compression_ratio: env("OPTIMA_LOOP_COMPRESSION_RATIO").unwrap_or(0.3),
+            sentence_repeats: env("OPTIMA_LOOP_SENTENCE_REPEATS").map_or(3, |repeats| repeats as usize),
+        }
+    }
+
+    pub fn detect(&self, output: &str) -> Option<OutputLoop> {
+        let words = words(output);
+        let ngram_repetition = ngram_repetition(&words);
+        let compression_ratio = compression_ratio(output);
+        let found = |start: usize, reason: String| OutputLoop { start, ngram_repetition, compression_ratio, reason };
+
+        // The same sentence over and over: cut at its second occurrence.
+        let mut seen: HashMap<String, (usize, usize)> = HashMap::new();
+        for (start, end) in split_claims(output) {
+            let normalized = words_in(&output[start..end]).join(" ");
+            if normalized.split(' ').count() < 3 {
+                continue;
+            }
+            let entry = seen.entry(normalized).or_insert((0, 0));
+            entry.0 += 1;
+            if entry.0 == 2 {
+                entry.1 = start;
+            }
+        }
+        if let Some((count, start)) = seen.values().filter(|(count, _)| *count >= self.sentence_repeats).min_by_key(|(_, start)| *start) {
+            return Some(found(*start, format!("a sentence repeats {} times", count)));
+        }
+
+        // A self-correction phrase more than once: cut at the second one.
+        let lower_output = output.to_ascii_lowercase();
+        let second_phrase = OUTPUT_LOOP_PHRASES
+            .iter()
+            .filter_map(|phrase| lower_output.match_indices(phrase).nth(1).map(|(idx, _)| (idx, *phrase)))
+            .min_by_key(|(idx, _)| *idx);
+        if let Some((start, phrase)) = second_phrase {
+            return Some(found(start, format!("\"{}\" repeats", phrase)));
+        }
+
+        // Low-entropy token loop: cut where a run of repeated n-grams begins.
+        if ngram_repetition >= self.max_ngram_repetition && compression_ratio <= self.min_compression_ratio {
+            let start = repeated_run_start(&words).unwrap_or(output.len());
+            return Some(found(start, format!("{:.0}% of {}-grams repeat", ngram_repetition * 100.0, LOOP_NGRAM)));
+        }
+        None
+    }
+}
+
+/// Lowercased alphanumeric words of `text` with their byte offsets.
+fn words(text: &str) -> Vec<(usize, String)> {
+    let mut words = Vec::new();
+    let mut current: Option<(usize, String)> = None;
+    for (idx, c) in text.char_indices() {
+        if c.is_alphanumeric() {
+            current.get_or_insert_with(|| (idx, String::new())).1.extend(c.to_lowercase());
+        } else if let Some(word) = current.take() {
+            words.push(word);
+        }
+    }
+    words.extend(current);
+    words
+}
+
+fn words_in(text: &str) -> Vec<String> {
+    words(text).into_iter().map(|(_, word)| word).collect()
+}
+
+fn ngram_repetition(words: &[(usize, String)]) -> f64 {
+    if words.len() < LOOP_NGRAM {
+        return 0.0;
+    }
+    let mut seen = HashSet::new();
+    let windows = words.windows(LOOP_NGRAM);
+    let total = windows.len();
+    let repeated = windows
+        .filter(|window| !seen.insert(window.iter().map(|(_, word)| word.as_str()).collect::<Vec<_>>()))
+        .count();
+    repeated as f64 / total as f64
+}
+
+fn compression_ratio(text: &str) -> f64 {
+    if text.len() < LOOP_MIN_COMPRESSIBLE_BYTES {
+        return 1.0;
+    }
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+    match encoder.write_all(text.as_bytes()).and_then(|_| encoder.finish()) {
+        Ok(compressed) => compressed.len() as f64 / text.len() as f64,
+        Err(_) => 1.0,
+    }
+}
+
+/// Byte offset of the first n-gram that repeats an earlier one and is
+/// followed by further repeats, i.e. the start of the loop proper.
+fn repeated_run_start(words: &[(usize, String)]) -> Option<usize> {
+    let mut seen = HashSet::new();
+    let repeats: Vec<bool> = words
+        .windows(LOOP_NGRAM)
+        .map(|window| !seen.insert(window.iter().map(|(_, word)| word.as_str()).collect::<Vec<_>>()))
+        .collect();
+    repeats
+        .windows(LOOP_NGRAM)
+        .position(|run| run.iter().all(|repeat| *repeat))
+        .map(|index| words[index].0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// (output, facts, score under the built-in pack). The Julia
+    /// implementation must produce the same scores.
+    const CONTRADICTION_CASES: &[(&str, &[&str], f64)] = &[
+        ("Paris is the capital of France.", &[], 0.0),
+        ("Supervised learning trains on labeled examples.", &["Supervised learning uses labeled data."], 0.0),
+        // Opposite pair: "fast" in the fact, "slow" in the output.
+        ("The cache is slow.", &["The cache is fast."], 0.5),
+        // "never" within 50 bytes of both "python" and "closures".
+        ("Python lacks support; closures are never allowed.", &["Python supports closures."], 0.8),
+        // The negation is more than 50 bytes away from "python".
+        ("Python is a popular language used by many people who never write C.", &["Python supports closures."], 0.0),
+        // Many hits; the score is capped.
+        ("Rust does not use ownership; it is garbage collected.", &["Rust uses ownership instead of garbage collection."], 1.0),
+    ];
+
+    const REFLECTION_CASES: &[(&str, bool)] = &[
+        ("What is the capital of France? Think about it.", true),
+        ("Reflect and think before answering.", true),
+        ("Think step by step.", false),
+        ("What is the boiling point of water?", false),
+    ];
+
+    fn facts(facts: &[&str]) -> Vec<String> {
+        facts.iter().map(|fact| fact.to_string()).collect()
+    }
+
+    #[test]
+    fn native_contradiction_scores() {
+        let rules = RuleSet::builtin();
+        for (output, case_facts, expected) in CONTRADICTION_CASES {
+            let score = rules.score(output, &facts(case_facts));
+            assert!((score - expected).abs() < 1e-9, "{:?} vs {:?}: got {}, expected {}", output, case_facts, score, expected);
+        }
+    }
+
+    #[test]
+    fn native_reflection_detection() {
+        for (prompt, expected) in REFLECTION_CASES {
+            assert_eq!(reflection_loop_native(prompt), *expected, "{:?}", prompt);
+        }
+    }
+
+    /// Needs a Julia installation; skipped when the runtime cannot start.
+    #[cfg(feature = "julia")]
+    #[tokio::test]
+    async fn julia_matches_native() {
+        if let Err(e) = crate::ffi::init_julia() {
+            eprintln!("skipping Julia parity check: {}", e);
+            return;
+        }
+        let rules = RuleSet::builtin();
+        for (output, case_facts, _) in CONTRADICTION_CASES {
+            let case_facts = facts(case_facts);
+            let julia = crate::ffi::check_for_contradiction(output, &case_facts).await.expect("Julia contradiction check");
+            let native = rules.score(output, &case_facts);
+            assert!((julia - native).abs() < 1e-9, "{:?}: Julia {} vs native {}", output, julia, native);
+        }
+        for (prompt, _) in REFLECTION_CASES {
+            let julia = crate::ffi::detect_reflection_loop(prompt).await.expect("Julia reflection detection");
+            assert_eq!(julia, reflection_loop_native(prompt), "{:?}", prompt);
+        }
+    }
+}
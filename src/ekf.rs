@@ -1,12 +1,16 @@
 use rocksdb::{DB, Options, IteratorMode};
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::error::Error;
 use std::path::Path;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use tracing::info;
 
-use crate::embedder::TinyBertEmbedder;
+use crate::embedder::{cosine_similarity, TinyBertEmbedder};
+
+/// Keys under this prefix hold auxiliary records (sessions, ...) rather than
+/// knowledge blobs, and are never part of the vector index.
+const RECORD_PREFIX: &str = "__record__:";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct KnowledgeBlob {
@@ -16,10 +20,21 @@ pub struct KnowledgeBlob {
     pub embedding: Vec<f32>,
 }
 
+/// A fact returned by `EKFStorage::retrieve`, with the key it is stored
+/// under so verdicts can point back at it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RetrievedFact {
+    pub key: String,
+    pub value: String,
+}
+
+/// Embeddings of stored facts, by key.
+type VectorIndex = Vec<(String, Vec<f32>)>;
+
 pub struct EKFStorage {
     db: DB,
     embedder: Arc<Mutex<TinyBertEmbedder>>,
-    vector_index: Arc<Mutex<Vec<(String, Vec<f32>)>>>,
+    vector_index: Arc<Mutex<VectorIndex>>,
 }
 
 impl EKFStorage {
@@ -36,11 +51,14 @@ impl EKFStorage {
         Ok(Self { db, embedder, vector_index: Arc::new(Mutex::new(vector_index)) })
     }
 
-    async fn load_index(db: &DB) -> Result<Vec<(String, Vec<f32>)>, Box<dyn Error>> {
+    async fn load_index(db: &DB) -> Result<VectorIndex, Box<dyn Error>> {
         let mut vector_index = Vec::new();
         let iter = db.iterator(IteratorMode::Start);
         for item in iter {
             let (key, value) = item?;
+            if key.starts_with(RECORD_PREFIX.as_bytes()) {
+                continue;
+            }
             if let Ok(blob) = serde_json::from_slice::<KnowledgeBlob>(&value) {
                 vector_index.push((String::from_utf8(key.to_vec())?, blob.embedding));
             }
@@ -49,6 +67,11 @@ impl EKFStorage {
     }
 
     pub async fn query(&self, prompt: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        Ok(self.retrieve(prompt).await?.into_iter().map(|fact| fact.value).collect())
+    }
+
+    /// Up to three facts most similar to `prompt`, above 0.6 similarity.
+    pub async fn retrieve(&self, prompt: &str) -> Result<Vec<RetrievedFact>, Box<dyn Error>> {
         let embedder_locked = self.embedder.lock().await;
         let prompt_embedding = embedder_locked.embed(prompt).await?;
 
@@ -56,7 +79,7 @@ impl EKFStorage {
         let mut similarities = Vec::new();
 
         for (key, embedding) in vector_index_locked.iter() {
-            let similarity = Self::cosine_similarity(&prompt_embedding, embedding);
+            let similarity = cosine_similarity(&prompt_embedding, embedding);
             similarities.push((key.clone(), similarity));
         }
 
@@ -68,7 +91,7 @@ impl EKFStorage {
             if sim > 0.6 {
                 if let Ok(Some(value_bytes)) = self.db.get(&key) {
                     if let Ok(blob) = serde_json::from_slice::<KnowledgeBlob>(&value_bytes) {
-                        results.push(blob.value);
+                        results.push(RetrievedFact { key, value: blob.value });
                     }
                 }
             }
@@ -76,27 +99,25 @@ impl EKFStorage {
         Ok(results)
     }
 
-    fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
-        if a.len() != b.len() || a.is_empty() {
-            return 0.0;
-        }
-
-        let mut dot_product = 0.0;
-        let mut norm_a = 0.0;
-        let mut norm_b = 0.0;
+    pub async fn put_record<T: Serialize>(&self, namespace: &str, key: &str, value: &T) -> Result<(), Box<dyn Error>> {
+        self.db.put(Self::record_key(namespace, key), serde_json::to_vec(value)?)?;
+        Ok(())
+    }
 
-        for i in 0..a.len() {
-            dot_product += a[i] * b[i];
-            norm_a += a[i] * a[i];
-            norm_b += b[i] * b[i];
+    pub async fn get_record<T: DeserializeOwned>(&self, namespace: &str, key: &str) -> Result<Option<T>, Box<dyn Error>> {
+        match self.db.get(Self::record_key(namespace, key))? {
+            Some(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            None => Ok(None),
         }
+    }
 
-        let denom = norm_a.sqrt() * norm_b.sqrt();
-        if denom == 0.0 {
-            0.0
-        } else {
-            dot_product / denom
-        }
+    pub async fn delete_record(&self, namespace: &str, key: &str) -> Result<(), Box<dyn Error>> {
+        self.db.delete(Self::record_key(namespace, key))?;
+        Ok(())
+    }
+
+    fn record_key(namespace: &str, key: &str) -> String {
+        format!("{}{}:{}", RECORD_PREFIX, namespace, key)
     }
 }
 
@@ -0,0 +1,262 @@
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::llm_backend::llm_env;
+use crate::resilience::HttpTransport;
+
+/// Point-in-time view of one endpoint, surfaced through `OptimaStats`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EndpointStats {
+    pub url: String,
+    pub weight: u32,
+    pub healthy: bool,
+    pub outstanding: usize,
+    pub requests: u64,
+    pub errors: u64,
+    pub avg_latency_ms: f64,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    requests: u64,
+    errors: u64,
+    total_latency: Duration,
+}
+
+#[derive(Debug)]
+pub struct Endpoint {
+    url: String,
+    weight: u32,
+    healthy: AtomicBool,
+    outstanding: AtomicUsize,
+    counters: Mutex<Counters>,
+}
+
+impl Endpoint {
+    fn new(url: String, weight: u32) -> Self {
+        Self {
+            url,
+            weight: weight.max(1),
+            healthy: AtomicBool::new(true),
+            outstanding: AtomicUsize::new(0),
+            counters: Mutex::new(Counters::default()),
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn is_available(&self, transport: &HttpTransport) -> bool {
+        self.healthy.load(Ordering::Relaxed) && !transport.is_circuit_open(&self.url)
+    }
+
+    /// Outstanding requests per unit of weight; lower is preferred.
+    fn load(&self) -> f64 {
+        self.outstanding.load(Ordering::Relaxed) as f64 / self.weight as f64
+    }
+
+    /// Mark a request as in flight until the returned guard is dropped.
+    pub fn begin(self: &Arc<Self>) -> InFlight {
+        self.outstanding.fetch_add(1, Ordering::Relaxed);
+        InFlight { endpoint: self.clone() }
+    }
+
+    pub fn record(&self, latency: Duration, ok: bool) {
+        let mut counters = self.counters.lock().unwrap();
+        counters.requests += 1;
+        counters.total_latency += latency;
+        if !ok {
+            counters.errors += 1;
+        }
+        self.healthy.store(ok, Ordering::Relaxed);
+    }
+
+    /// Count a request the endpoint answered but turned down, e.g. with a
+    /// 4xx. The endpoint stays healthy.
+    pub fn record_rejected(&self, latency: Duration) {
+        let mut counters = self.counters.lock().unwrap();
+        counters.requests += 1;
+        counters.total_latency += latency;
+        counters.errors += 1;
+    }
+
+    fn stats(&self) -> EndpointStats {
+        let counters = self.counters.lock().unwrap();
+        let avg_latency_ms = if counters.requests > 0 {
+            counters.total_latency.as_secs_f64() * 1000.0 / counters.requests as f64
+        } else {
+            0.0
+        };
+        EndpointStats {
+            url: self.url.clone(),
+            weight: self.weight,
+            healthy: self.healthy.load(Ordering::Relaxed),
+            outstanding: self.outstanding.load(Ordering::Relaxed),
+            requests: counters.requests,
+            errors: counters.errors,
+            avg_latency_ms,
+        }
+    }
+}
+
+/// Keeps an endpoint's outstanding-request count raised while alive.
+pub struct InFlight {
+    endpoint: Arc<Endpoint>,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.endpoint.outstanding.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Weighted set of inference replicas with least-outstanding-requests
+/// selection and health tracking.
+#[derive(Debug)]
+pub struct EndpointPool {
+    endpoints: Vec<Arc<Endpoint>>,
+}
+
+impl EndpointPool {
+    /// Read `LLM_API_ENDPOINTS` as `url[|weight],url[|weight],...`, falling
+    /// back to the single `LLM_API_ENDPOINT` and then to `default_url`.
+    /// Both can be scoped to a cascade tier.
+    pub fn from_env(default_url: &str, tier: Option<&str>) -> Self {
+        let spec = llm_env(tier, "API_ENDPOINTS")
+            .or_else(|| llm_env(tier, "API_ENDPOINT"))
+            .unwrap_or_else(|| default_url.to_string());
+        Self::parse(&spec)
+    }
+
+    pub fn parse(spec: &str) -> Self {
+        let endpoints = spec
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| match entry.rsplit_once('|') {
+                Some((url, weight)) => Endpoint::new(url.trim().to_string(), weight.trim().parse().unwrap_or(1)),
+                None => Endpoint::new(entry.to_string(), 1),
+            })
+            .map(Arc::new)
+            .collect();
+        Self { endpoints }
+    }
+
+    pub fn len(&self) -> usize {
+        self.endpoints.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.endpoints.is_empty()
+    }
+
+    /// How many endpoints are healthy with a closed circuit.
+    pub fn available(&self, transport: &HttpTransport) -> usize {
+        self.endpoints
+            .iter()
+            .filter(|endpoint| endpoint.is_available(transport))
+            .count()
+    }
+
+    /// Endpoints in the order they should be tried: healthy endpoints with a
+    /// closed circuit first, least loaded first; the rest as a last resort.
+    pub fn candidates(&self, transport: &HttpTransport) -> Vec<Arc<Endpoint>> {
+        let mut ranked: Vec<(bool, f64, Arc<Endpoint>)> = self
+            .endpoints
+            .iter()
+            .map(|endpoint| {
+                (!endpoint.is_available(transport), endpoint.load(), endpoint.clone())
+            })
+            .collect();
+        ranked.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.total_cmp(&b.1)));
+        ranked.into_iter().map(|(_, _, endpoint)| endpoint).collect()
+    }
+
+    pub fn stats(&self) -> Vec<EndpointStats> {
+        self.endpoints.iter().map(|endpoint| endpoint.stats()).collect()
+    }
+
+    /// Probe every endpoint's health route (`LLM_HEALTH_PATH`, default
+    /// `/health`, on the endpoint's origin) every `interval`.
+    pub async fn run_health_checks(self: Arc<Self>, transport: Arc<HttpTransport>, interval: Duration) {
+        let health_path = std::env::var("LLM_HEALTH_PATH").unwrap_or_else(|_| "/health".to_string());
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            for endpoint in &self.endpoints {
+                let Some(health_url) = health_url(&endpoint.url, &health_path) else {
+                    continue;
+                };
+                let healthy = transport.probe(&health_url).await;
+                let was_healthy = endpoint.healthy.swap(healthy, Ordering::Relaxed);
+                if healthy != was_healthy {
+                    if healthy {
+                        info!("Endpoint {} is healthy again", endpoint.url);
+                    } else {
+                        warn!("Endpoint {} failed its health check", endpoint.url);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn health_url(endpoint: &str, health_path: &str) -> Option<String> {
+    let mut url = Url::parse(endpoint).ok()?;
+    url.set_path(health_path);
+    url.set_query(None);
+    Some(url.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resilience::RetryPolicy;
+    use reqwest::Client;
+
+    fn transport() -> HttpTransport {
+        let retry = RetryPolicy { max_retries: 0, base_delay: Duration::ZERO, max_delay: Duration::ZERO };
+        HttpTransport::new(Client::new(), retry, Duration::from_secs(5), 5, Duration::from_secs(30))
+    }
+
+    fn urls(endpoints: &[Arc<Endpoint>]) -> Vec<&str> {
+        endpoints.iter().map(|endpoint| endpoint.url()).collect()
+    }
+
+    #[test]
+    fn parse_reads_weights() {
+        let pool = EndpointPool::parse(" http://a|3, http://b ,,http://c|x, http://d|0");
+        let weights: Vec<(&str, u32)> = pool.endpoints.iter().map(|endpoint| (endpoint.url(), endpoint.weight)).collect();
+        // Unparseable weights count as 1 and a zero weight is raised to 1.
+        assert_eq!(weights, vec![("http://a", 3), ("http://b", 1), ("http://c", 1), ("http://d", 1)]);
+    }
+
+    #[test]
+    fn candidates_prefer_least_outstanding_per_weight() {
+        let pool = EndpointPool::parse("http://a|1,http://b|4,http://c|1");
+        let transport = transport();
+        let _a = pool.endpoints[0].begin();
+        let _b = [pool.endpoints[1].begin(), pool.endpoints[1].begin()];
+        // Loads: a 1/1, b 2/4, c 0/1.
+        assert_eq!(urls(&pool.candidates(&transport)), vec!["http://c", "http://b", "http://a"]);
+    }
+
+    #[test]
+    fn unhealthy_endpoints_are_tried_last() {
+        let pool = EndpointPool::parse("http://a,http://b");
+        let transport = transport();
+        pool.endpoints[0].record(Duration::from_millis(5), false);
+        assert_eq!(pool.available(&transport), 1);
+        assert_eq!(urls(&pool.candidates(&transport)), vec!["http://b", "http://a"]);
+
+        // A rejected request counts as an error but not against health.
+        pool.endpoints[1].record_rejected(Duration::from_millis(5));
+        assert_eq!(pool.available(&transport), 1);
+        assert_eq!(pool.stats()[1].errors, 1);
+    }
+}
@@ -0,0 +1,34 @@
+use serde::{Deserialize, Serialize};
+
+use crate::generation::GenerationParams;
+use crate::prompt::ChatMessage;
+
+/// What a single pipeline stage decided for a prompt, and why.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StageDecision {
+    pub stage: String,
+    pub applied: bool,
+    pub reason: String,
+}
+
+impl StageDecision {
+    pub fn new(stage: &str, applied: bool, reason: impl Into<String>) -> Self {
+        Self { stage: stage.to_string(), applied, reason: reason.into() }
+    }
+}
+
+/// Result of a dry run through the pipeline: everything up to, but not
+/// including, the LLM call.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExplainReport {
+    pub original_prompt: String,
+    /// The prompt that would be sent to the LLM, including injected EKF
+    /// knowledge and HHTC surrogate tokens (`#<hash>`).
+    pub final_prompt: String,
+    /// The same prompt as the chat messages handed to the backend.
+    pub final_messages: Vec<ChatMessage>,
+    pub generation_params: GenerationParams,
+    pub compression_ratio: f64,
+    pub ekf_knowledge: Vec<String>,
+    pub stages: Vec<StageDecision>,
+}
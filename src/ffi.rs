@@ -2,27 +2,52 @@ use jlrs::prelude::*;
 use once_cell::sync::OnceCell;
 use std::sync::Arc;
 use tokio::sync::Mutex;
-use tracing::info;
+use tracing::{info, warn};
 use std::error::Error;
 
+use crate::health::SubsystemStatus;
+
 static JULIA: OnceCell<Arc<Mutex<AsyncJulia>>> = OnceCell::new();
+static JULIA_INIT_ERROR: OnceCell<String> = OnceCell::new();
 const JULIA_CODE: &str = include_str!("optimacore.jl");
 
-pub fn init_julia() {
-    JULIA.get_or_init(|| {
-        info!("Initializing Julia runtime via jlrs...");
-        let julia = unsafe {
-            JuliaBuilder::new()
-                .init_async::<jlrs::runtime::AsyncRuntime>()
-                .unwrap()
-                .async_run(|mut frame| async move {
-                    frame.include_string(JULIA_CODE).unwrap();
+pub fn init_julia() -> Result<(), Box<dyn Error>> {
+    if JULIA.get().is_some() {
+        return Ok(());
+    }
+
+    info!("Initializing Julia runtime via jlrs...");
+    let started = unsafe {
+        JuliaBuilder::new()
+            .init_async::<jlrs::runtime::AsyncRuntime>()
+            .and_then(|builder| {
+                builder.async_run(|mut frame| async move {
+                    frame.include_string(JULIA_CODE)?;
                     Ok(())
                 })
-                .unwrap()
-        };
-        Arc::new(Mutex::new(julia))
-    });
+            })
+    };
+
+    match started {
+        Ok(julia) => {
+            let _ = JULIA.set(Arc::new(Mutex::new(julia)));
+            Ok(())
+        }
+        Err(e) => {
+            let _ = JULIA_INIT_ERROR.set(e.to_string());
+            Err(format!("Failed to initialize Julia runtime: {}", e).into())
+        }
+    }
+}
+
+pub fn julia_status() -> SubsystemStatus {
+    if JULIA.get().is_some() {
+        SubsystemStatus::Available
+    } else if let Some(reason) = JULIA_INIT_ERROR.get() {
+        SubsystemStatus::Disabled(reason.clone())
+    } else {
+        SubsystemStatus::Disabled("Julia runtime not initialized".to_string())
+    }
 }
 
 async fn run_julia_function_bool(name: &str, arg: &str) -> Result<bool, Box<dyn Error>> {
@@ -56,10 +81,20 @@ async fn run_julia_function_contradiction(output: &str, facts: &[String]) -> Res
     Ok(result)
 }
 
-pub async fn detect_reflection_loop(prompt: &str) -> bool {
-    run_julia_function_bool("detect_reflection_loop", prompt).await.unwrap_or(false)
+/// Returns `None` when the Julia runtime is unavailable or the call failed.
+pub async fn detect_reflection_loop(prompt: &str) -> Option<bool> {
+    JULIA.get()?;
+    run_julia_function_bool("detect_reflection_loop", prompt)
+        .await
+        .map_err(|e| warn!("Reflection detection unavailable: {}", e))
+        .ok()
 }
 
-pub async fn check_for_contradiction(output: &str, facts: &[String]) -> f64 {
-    run_julia_function_contradiction(output, facts).await.unwrap_or(0.0)
-}
\ No newline at end of file
+/// Returns `None` when the Julia runtime is unavailable or the call failed.
+pub async fn check_for_contradiction(output: &str, facts: &[String]) -> Option<f64> {
+    JULIA.get()?;
+    run_julia_function_contradiction(output, facts)
+        .await
+        .map_err(|e| warn!("Contradiction check unavailable: {}", e))
+        .ok()
+}
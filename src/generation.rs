@@ -0,0 +1,246 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+
+/// A sampling field that not every backend accepts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GenerationParam {
+    Stop,
+    Seed,
+    PresencePenalty,
+    FrequencyPenalty,
+    LogitBias,
+}
+
+impl GenerationParam {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GenerationParam::Stop => "stop",
+            GenerationParam::Seed => "seed",
+            GenerationParam::PresencePenalty => "presence_penalty",
+            GenerationParam::FrequencyPenalty => "frequency_penalty",
+            GenerationParam::LogitBias => "logit_bias",
+        }
+    }
+}
+
+/// Why a set of generation parameters was rejected before sending.
+#[derive(Debug, Clone)]
+pub enum ParamError {
+    OutOfRange { field: &'static str, value: f64, min: f64, max: f64 },
+    Unsupported { backend: &'static str, field: &'static str },
+}
+
+impl fmt::Display for ParamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParamError::OutOfRange { field, value, min, max } => {
+                write!(f, "generation parameter `{}` = {} is outside [{}, {}]", field, value, min, max)
+            }
+            ParamError::Unsupported { backend, field } => {
+                write!(f, "the {} backend does not support generation parameter `{}`", backend, field)
+            }
+        }
+    }
+}
+
+impl Error for ParamError {}
+
+/// Sampling settings for one LLM call. `max_tokens`, `temperature` and
+/// `top_p` are understood by every backend; the rest are sent only when set.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GenerationParams {
+    pub max_tokens: u32,
+    pub temperature: f64,
+    pub top_p: f64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f64>,
+    /// Token (id or text, depending on the backend) to bias.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub logit_bias: BTreeMap<String, f64>,
+}
+
+impl Default for GenerationParams {
+    fn default() -> Self {
+        Self {
+            max_tokens: 256,
+            temperature: 0.7,
+            top_p: 0.9,
+            stop: Vec::new(),
+            seed: None,
+            presence_penalty: None,
+            frequency_penalty: None,
+            logit_bias: BTreeMap::new(),
+        }
+    }
+}
+
+impl GenerationParams {
+    /// Defaults overridden by `LLM_MAX_TOKENS`, `LLM_TEMPERATURE`,
+    /// `LLM_TOP_P`, `LLM_STOP` (comma-separated), `LLM_SEED`,
+    /// `LLM_PRESENCE_PENALTY`, `LLM_FREQUENCY_PENALTY` and `LLM_LOGIT_BIAS`
+    /// (`token:bias,...`).
+    pub fn from_env() -> Self {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    /// `from_env` reading variables through `var`. Unparseable values keep
+    /// the default.
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Self {
+        fn parse<T: std::str::FromStr>(value: Option<String>) -> Option<T> {
+            value.and_then(|v| v.trim().parse().ok())
+        }
+        let defaults = Self::default();
+        Self {
+            max_tokens: parse(var("LLM_MAX_TOKENS")).unwrap_or(defaults.max_tokens),
+            temperature: parse(var("LLM_TEMPERATURE")).unwrap_or(defaults.temperature),
+            top_p: parse(var("LLM_TOP_P")).unwrap_or(defaults.top_p),
+            stop: var("LLM_STOP")
+                .map(|v| v.split(',').filter(|s| !s.is_empty()).map(str::to_string).collect())
+                .unwrap_or_default(),
+            seed: parse(var("LLM_SEED")),
+            presence_penalty: parse(var("LLM_PRESENCE_PENALTY")),
+            frequency_penalty: parse(var("LLM_FREQUENCY_PENALTY")),
+            logit_bias: var("LLM_LOGIT_BIAS")
+                .map(|v| {
+                    v.split(',')
+                        .filter_map(|entry| entry.rsplit_once(':'))
+                        .filter_map(|(token, bias)| Some((token.trim().to_string(), bias.trim().parse().ok()?)))
+                        .collect()
+                })
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Optional fields that carry a value in these params.
+    pub fn used_optional(&self) -> Vec<GenerationParam> {
+        let mut used = Vec::new();
+        if !self.stop.is_empty() {
+            used.push(GenerationParam::Stop);
+        }
+        if self.seed.is_some() {
+            used.push(GenerationParam::Seed);
+        }
+        if self.presence_penalty.is_some() {
+            used.push(GenerationParam::PresencePenalty);
+        }
+        if self.frequency_penalty.is_some() {
+            used.push(GenerationParam::FrequencyPenalty);
+        }
+        if !self.logit_bias.is_empty() {
+            used.push(GenerationParam::LogitBias);
+        }
+        used
+    }
+
+    /// Check value ranges, and that `backend` accepts every optional field
+    /// that is set.
+    pub fn validate(&self, backend: &'static str, supported: &[GenerationParam]) -> Result<(), ParamError> {
+        check_range("max_tokens", self.max_tokens as f64, 1.0, u32::MAX as f64)?;
+        check_range("temperature", self.temperature, 0.0, 2.0)?;
+        check_range("top_p", self.top_p, 0.0, 1.0)?;
+        if let Some(penalty) = self.presence_penalty {
+            check_range("presence_penalty", penalty, -2.0, 2.0)?;
+        }
+        if let Some(penalty) = self.frequency_penalty {
+            check_range("frequency_penalty", penalty, -2.0, 2.0)?;
+        }
+        for bias in self.logit_bias.values() {
+            check_range("logit_bias", *bias, -100.0, 100.0)?;
+        }
+        match self.used_optional().into_iter().find(|param| !supported.contains(param)) {
+            Some(param) => Err(ParamError::Unsupported { backend, field: param.as_str() }),
+            None => Ok(()),
+        }
+    }
+}
+
+fn check_range(field: &'static str, value: f64, min: f64, max: f64) -> Result<(), ParamError> {
+    if value.is_finite() && (min..=max).contains(&value) {
+        Ok(())
+    } else {
+        Err(ParamError::OutOfRange { field, value, min, max })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: &[GenerationParam] = &[
+        GenerationParam::Stop,
+        GenerationParam::Seed,
+        GenerationParam::PresencePenalty,
+        GenerationParam::FrequencyPenalty,
+        GenerationParam::LogitBias,
+    ];
+
+    fn out_of_range(params: GenerationParams) -> Option<&'static str> {
+        match params.validate("test", ALL) {
+            Err(ParamError::OutOfRange { field, .. }) => Some(field),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn defaults_are_valid_everywhere() {
+        assert!(GenerationParams::default().validate("generic", &[]).is_ok());
+    }
+
+    #[test]
+    fn values_outside_their_range_are_rejected() {
+        let defaults = GenerationParams::default;
+        assert_eq!(out_of_range(GenerationParams { max_tokens: 0, ..defaults() }), Some("max_tokens"));
+        assert_eq!(out_of_range(GenerationParams { temperature: 2.5, ..defaults() }), Some("temperature"));
+        assert_eq!(out_of_range(GenerationParams { temperature: -0.1, ..defaults() }), Some("temperature"));
+        assert_eq!(out_of_range(GenerationParams { temperature: f64::NAN, ..defaults() }), Some("temperature"));
+        assert_eq!(out_of_range(GenerationParams { top_p: 1.1, ..defaults() }), Some("top_p"));
+        assert_eq!(out_of_range(GenerationParams { presence_penalty: Some(-2.5), ..defaults() }), Some("presence_penalty"));
+        assert_eq!(out_of_range(GenerationParams { frequency_penalty: Some(3.0), ..defaults() }), Some("frequency_penalty"));
+        let logit_bias = BTreeMap::from([("42".to_string(), 150.0)]);
+        assert_eq!(out_of_range(GenerationParams { logit_bias, ..defaults() }), Some("logit_bias"));
+
+        // The bounds themselves are allowed.
+        let edges = GenerationParams { temperature: 2.0, top_p: 0.0, presence_penalty: Some(-2.0), frequency_penalty: Some(2.0), ..defaults() };
+        assert!(edges.validate("test", ALL).is_ok());
+    }
+
+    #[test]
+    fn unsupported_fields_are_rejected() {
+        let params = GenerationParams { seed: Some(7), ..GenerationParams::default() };
+        let error = params.validate("generic", &[GenerationParam::Stop]).unwrap_err();
+        assert!(matches!(error, ParamError::Unsupported { backend: "generic", field: "seed" }), "{}", error);
+        assert!(params.validate("openai", ALL).is_ok());
+    }
+
+    #[test]
+    fn from_vars_parses_every_field() {
+        let vars = BTreeMap::from([
+            ("LLM_MAX_TOKENS", "512"),
+            ("LLM_TEMPERATURE", " 0.2 "),
+            ("LLM_TOP_P", "not a number"),
+            ("LLM_STOP", "END,,###"),
+            ("LLM_SEED", "7"),
+            ("LLM_FREQUENCY_PENALTY", "0.5"),
+            ("LLM_LOGIT_BIAS", "50256:-100, hello : 5,broken"),
+        ]);
+        let params = GenerationParams::from_vars(|name| vars.get(name).map(|v| v.to_string()));
+
+        assert_eq!(params.max_tokens, 512);
+        assert_eq!(params.temperature, 0.2);
+        assert_eq!(params.top_p, 0.9);
+        assert_eq!(params.stop, ["END", "###"]);
+        assert_eq!(params.seed, Some(7));
+        assert_eq!(params.presence_penalty, None);
+        assert_eq!(params.frequency_penalty, Some(0.5));
+        assert_eq!(params.logit_bias, BTreeMap::from([("50256".to_string(), -100.0), ("hello".to_string(), 5.0)]));
+        assert_eq!(params.used_optional(), [GenerationParam::Stop, GenerationParam::Seed, GenerationParam::FrequencyPenalty, GenerationParam::LogitBias]);
+    }
+}
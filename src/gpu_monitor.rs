@@ -14,14 +14,15 @@ pub struct GPUMonitor {
 
 impl GPUMonitor {
     pub async fn new() -> Result<Self, Box<dyn Error>> {
-        let nvml_instance = NVML.get_or_init(|| {
+        let nvml_instance = NVML.get_or_try_init(|| -> Result<_, Box<dyn Error>> {
             info!("Initializing NVML library for real GPU monitoring...");
-            Arc::new(Mutex::new(Nvml::init().expect(
-                "Failed to initialize NVML. Ensure you have an NVIDIA GPU and drivers.",
-            )))
-        });
+            let nvml = Nvml::init().map_err(|e| {
+                format!("Failed to initialize NVML ({}). Ensure you have an NVIDIA GPU and drivers.", e)
+            })?;
+            Ok(Arc::new(Mutex::new(nvml)))
+        })?;
 
-        let nvml = nvml_instance.lock().unwrap();
+        let nvml = nvml_instance.lock().map_err(|_| "NVML mutex poisoned")?;
         let device = nvml.device_by_index(0)?;
 
         Ok(Self {
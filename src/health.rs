@@ -0,0 +1,41 @@
+use serde::{Deserialize, Serialize};
+
+/// Runtime state of an optional subsystem. `Degraded` and `Disabled` carry a
+/// short human-readable reason.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SubsystemStatus {
+    Available,
+    Degraded(String),
+    Disabled(String),
+}
+
+impl SubsystemStatus {
+    pub fn is_available(&self) -> bool {
+        matches!(self, SubsystemStatus::Available)
+    }
+
+    /// A subsystem is usable unless it has been disabled outright.
+    pub fn is_usable(&self) -> bool {
+        !matches!(self, SubsystemStatus::Disabled(_))
+    }
+}
+
+/// Snapshot of every subsystem `OptimaCore` depends on.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthReport {
+    pub hhtc: SubsystemStatus,
+    pub ekf: SubsystemStatus,
+    pub julia_runtime: SubsystemStatus,
+    pub gpu_monitor: SubsystemStatus,
+    pub llm_client: SubsystemStatus,
+}
+
+/// Which protections actually ran while serving a single request.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Protections {
+    pub reflection_detection: bool,
+    pub hhtc_compression: bool,
+    pub ekf_retrieval: bool,
+    pub verification: bool,
+    pub gpu_metrics: bool,
+}
@@ -2,6 +2,7 @@ use blake3::hash;
 use flate2::{write::GzEncoder, Compression};
 use lru::LruCache;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::error::Error;
 use std::io::Write;
 use std::num::NonZeroUsize;
@@ -34,6 +35,16 @@ impl HHTCEngine {
     }
 
     pub async fn compress(&mut self, text: &str) -> (String, f64) {
+        self.run(text, true).await
+    }
+
+    /// Compress `text` exactly as `compress` would, without touching the
+    /// chunk cache. Chunks repeated within `text` itself are still replaced.
+    pub async fn preview(&self, text: &str) -> (String, f64) {
+        self.run(text, false).await
+    }
+
+    async fn run(&self, text: &str, commit: bool) -> (String, f64) {
         let tokens: Vec<&str> = text.split_whitespace().collect();
         let original_tokens_count = tokens.len();
 
@@ -43,6 +54,7 @@ impl HHTCEngine {
 
         let mut compressed_output_string = String::new();
         let mut actual_compressed_token_count = 0;
+        let mut seen_in_preview = HashSet::new();
 
         let mut current_token_idx = 0;
         while current_token_idx < original_tokens_count {
@@ -56,26 +68,30 @@ impl HHTCEngine {
             let hash_id = u64::from_le_bytes(chunk_hash_bytes);
 
             let mut lru_cache = self.cache.lock().await;
-            if lru_cache.contains(&hash_id) {
+            if lru_cache.contains(&hash_id) || seen_in_preview.contains(&hash_id) {
                 compressed_output_string.push_str(&format!("#{} ", hash_id));
                 actual_compressed_token_count += 1;
             } else {
-                let embedder_locked = self.embedder.lock().await;
-                let embedding = embedder_locked.embed(&chunk_text).await.unwrap_or_else(|e| {
-                    info!("Error computing embedding for HHTC chunk: {:?}", e);
-                    vec![0.0; embedder_locked.dim()]
-                });
-
-                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
-                encoder.write_all(chunk_text.as_bytes()).unwrap();
-                let compressed_kv_data = encoder.finish().unwrap();
-
-                let precomp_state = PrecompState {
-                    compressed_kv: compressed_kv_data,
-                    embedding,
-                };
-
-                lru_cache.put(hash_id, precomp_state);
+                if commit {
+                    let embedder_locked = self.embedder.lock().await;
+                    let embedding = embedder_locked.embed(&chunk_text).await.unwrap_or_else(|e| {
+                        info!("Error computing embedding for HHTC chunk: {:?}", e);
+                        vec![0.0; embedder_locked.dim()]
+                    });
+
+                    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                    encoder.write_all(chunk_text.as_bytes()).unwrap();
+                    let compressed_kv_data = encoder.finish().unwrap();
+
+                    let precomp_state = PrecompState {
+                        compressed_kv: compressed_kv_data,
+                        embedding,
+                    };
+
+                    lru_cache.put(hash_id, precomp_state);
+                } else {
+                    seen_in_preview.insert(hash_id);
+                }
                 compressed_output_string.push_str(&chunk_text);
                 compressed_output_string.push(' ');
                 actual_compressed_token_count += chunk_size_for_current_segment;
@@ -88,4 +104,3 @@ impl HHTCEngine {
         (compressed_output_string.trim().to_string(), compression_ratio)
     }
 }
-
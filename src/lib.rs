@@ -2,8 +2,31 @@ pub mod core;
 pub mod hhtc;
 pub mod ekf;
 pub mod verifier;
+pub mod numeric;
+pub mod structured;
+pub mod policy;
+pub mod pii;
+pub mod rollback;
 pub mod gpu_monitor;
 pub mod llm_integration;
+pub mod cascade;
+pub mod llm_backend;
+pub mod replay;
+pub mod generation;
+pub mod usage;
+pub mod prompt;
+pub mod resilience;
+pub mod endpoint_pool;
+#[cfg(feature = "julia")]
 pub mod ffi;
+pub mod detectors;
+pub mod rules;
 pub mod embedder;
-
+pub mod health;
+pub mod explain;
+pub mod session;
+pub mod shadow;
+pub mod request;
+pub mod stats;
+pub mod response_cache;
+pub mod streaming;
@@ -0,0 +1,778 @@
+use async_trait::async_trait;
+use futures_util::{stream, Stream, StreamExt};
+use reqwest::Response;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::generation::{GenerationParam, GenerationParams};
+use crate::prompt::{flatten_messages, ChatMessage, Role};
+use crate::replay::{RecordingBackend, ReplayBackend};
+use crate::resilience::HttpTransport;
+use crate::usage::{estimate_tokens, TokenUsage};
+
+pub type StreamError = Box<dyn Error + Send + Sync>;
+
+/// Incremental text chunks as they arrive from the backend.
+pub type TokenStream = Pin<Box<dyn Stream<Item = Result<String, StreamError>> + Send>>;
+
+/// A complete, non-streamed response.
+#[derive(Debug, Clone)]
+pub struct Completion {
+    pub text: String,
+    /// Token counts as reported by the server, when it reports them.
+    pub usage: Option<TokenUsage>,
+}
+
+/// An inference server protocol. Each adapter serializes the chat messages in
+/// the server's native format and extracts the generated text from its reply.
+#[async_trait]
+pub trait LlmBackend: Send + Sync {
+    fn name(&self) -> &'static str;
+    /// Endpoint used when none is configured.
+    fn default_endpoint(&self) -> &'static str;
+    /// Model requested from the server, for backends that name one.
+    fn model(&self) -> Option<&str> {
+        None
+    }
+    /// Optional generation fields the server accepts; `max_tokens`,
+    /// `temperature` and `top_p` are always supported.
+    fn supported_params(&self) -> &'static [GenerationParam];
+    async fn generate(&self, endpoint: &str, messages: &[ChatMessage], params: &GenerationParams) -> Result<Completion, Box<dyn Error>>;
+
+    /// Stream the response. Backends without a streaming protocol yield the
+    /// full response as a single chunk.
+    async fn generate_stream(&self, endpoint: &str, messages: &[ChatMessage], params: &GenerationParams) -> Result<TokenStream, Box<dyn Error>> {
+        let text = self.generate(endpoint, messages, params).await?.text;
+        Ok(Box::pin(stream::once(async move { Ok(text) })))
+    }
+}
+
+/// Read `LLM_<TIER>_<name>` for a cascade tier, falling back to the shared
+/// `LLM_<name>`.
+pub fn llm_env(tier: Option<&str>, name: &str) -> Option<String> {
+    let scoped = tier.and_then(|tier| {
+        let tier: String = tier.chars().map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' }).collect();
+        std::env::var(format!("LLM_{}_{}", tier, name)).ok()
+    });
+    scoped.or_else(|| std::env::var(format!("LLM_{}", name)).ok())
+}
+
+/// Build the backend selected by `LLM_BACKEND` (`generic`, `openai`,
+/// `ollama`, `llamacpp`, `tgi`, `record` or `replay`). `LLM_MODEL` and
+/// `LLM_API_KEY` override the per-backend defaults. `record` wraps the
+/// backend named by `LLM_RECORD_BACKEND` (default `generic`); `record` and
+/// `replay` share the fixture file `LLM_FIXTURE_PATH` (default
+/// `./llm_fixtures.json`). Each can be scoped to a cascade tier; see
+/// `llm_env`.
+pub fn backend_from_env(transport: Arc<HttpTransport>, tier: Option<&str>) -> Result<Box<dyn LlmBackend>, Box<dyn Error>> {
+    let kind = llm_env(tier, "BACKEND").unwrap_or_else(|| "generic".to_string());
+    let fixture_path = || PathBuf::from(llm_env(tier, "FIXTURE_PATH").unwrap_or_else(|| "./llm_fixtures.json".to_string()));
+    match kind.to_lowercase().as_str() {
+        "record" => {
+            let inner_kind = llm_env(tier, "RECORD_BACKEND").unwrap_or_else(|| "generic".to_string());
+            let inner = build_backend(&inner_kind, transport, tier)?;
+            Ok(Box::new(RecordingBackend::new(inner, fixture_path())?))
+        }
+        "replay" => Ok(Box::new(ReplayBackend::new(fixture_path())?)),
+        other => build_backend(other, transport, tier),
+    }
+}
+
+fn build_backend(kind: &str, transport: Arc<HttpTransport>, tier: Option<&str>) -> Result<Box<dyn LlmBackend>, Box<dyn Error>> {
+    let model = llm_env(tier, "MODEL");
+    let backend: Box<dyn LlmBackend> = match kind.to_lowercase().as_str() {
+        "generic" => Box::new(GenericBackend {
+            transport: transport.clone(),
+        }),
+        "openai" => Box::new(OpenAiBackend {
+            transport: transport.clone(),
+            model: model.unwrap_or_else(|| "gpt-4o-mini".to_string()),
+            api_key: llm_env(tier, "API_KEY"),
+        }),
+        "ollama" => Box::new(OllamaBackend {
+            transport: transport.clone(),
+            model: model.unwrap_or_else(|| "llama3".to_string()),
+        }),
+        "llamacpp" | "llama.cpp" => Box::new(LlamaCppBackend {
+            transport: transport.clone(),
+        }),
+        "tgi" => Box::new(TgiBackend {
+            transport: transport.clone(),
+        }),
+        other => return Err(format!("Unknown LLM_BACKEND '{}'", other).into()),
+    };
+    Ok(backend)
+}
+
+async fn post_json(transport: &HttpTransport, url: &str, payload: &Value, bearer: Option<&str>) -> Result<Value, Box<dyn Error>> {
+    let response = transport.post_json(url, payload, bearer, false).await?;
+    Ok(response.json().await?)
+}
+
+/// Wire format of a streaming response body.
+#[derive(Debug, Clone, Copy)]
+enum StreamFormat {
+    /// Server-sent events: `data: {...}` lines, optionally ending in `data: [DONE]`.
+    Sse,
+    /// One JSON object per line.
+    NdJson,
+}
+
+enum StreamLine {
+    Token(String),
+    Done,
+    Skip,
+}
+
+/// Picks the text (and end-of-stream flag) out of one streamed JSON event.
+type ChunkExtractor = fn(&Value) -> (Option<String>, bool);
+
+fn parse_stream_line(line: &str, format: StreamFormat, extract: ChunkExtractor) -> StreamLine {
+    let data = match format {
+        StreamFormat::Sse => match line.strip_prefix("data:") {
+            Some(data) => data.trim(),
+            None => return StreamLine::Skip,
+        },
+        StreamFormat::NdJson => line,
+    };
+    if data.is_empty() {
+        return StreamLine::Skip;
+    }
+    if data == "[DONE]" {
+        return StreamLine::Done;
+    }
+    match serde_json::from_str::<Value>(data) {
+        Ok(event) => match extract(&event) {
+            (Some(text), _) if !text.is_empty() => StreamLine::Token(text),
+            (_, true) => StreamLine::Done,
+            _ => StreamLine::Skip,
+        },
+        Err(_) => StreamLine::Skip,
+    }
+}
+
+/// Turn a streaming HTTP body into text chunks. Lines are split on raw bytes
+/// so multi-byte characters spanning network chunks stay intact. The stream
+/// fails if no data arrives for `read_timeout`.
+fn token_stream(response: Response, read_timeout: Duration, format: StreamFormat, extract: ChunkExtractor) -> TokenStream {
+    let bytes = Box::pin(response.bytes_stream());
+    let state = (bytes, Vec::<u8>::new(), VecDeque::<String>::new(), false);
+
+    Box::pin(stream::unfold(state, move |(mut bytes, mut buffer, mut pending, mut done)| async move {
+        loop {
+            if let Some(token) = pending.pop_front() {
+                return Some((Ok(token), (bytes, buffer, pending, done)));
+            }
+            if done {
+                return None;
+            }
+            let next = match tokio::time::timeout(read_timeout, bytes.next()).await {
+                Ok(next) => next,
+                Err(_) => {
+                    done = true;
+                    let error: StreamError = format!("no data from LLM stream for {:?}", read_timeout).into();
+                    return Some((Err(error), (bytes, buffer, pending, done)));
+                }
+            };
+            match next {
+                Some(Ok(data)) => {
+                    buffer.extend_from_slice(&data);
+                    while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
+                        let line: Vec<u8> = buffer.drain(..=pos).collect();
+                        match parse_stream_line(String::from_utf8_lossy(&line).trim(), format, extract) {
+                            StreamLine::Token(token) => pending.push_back(token),
+                            StreamLine::Done => {
+                                done = true;
+                                break;
+                            }
+                            StreamLine::Skip => {}
+                        }
+                    }
+                }
+                Some(Err(e)) => {
+                    done = true;
+                    return Some((Err(e.into()), (bytes, buffer, pending, done)));
+                }
+                None => {
+                    done = true;
+                    let line = std::mem::take(&mut buffer);
+                    if let StreamLine::Token(token) = parse_stream_line(String::from_utf8_lossy(&line).trim(), format, extract) {
+                        pending.push_back(token);
+                    }
+                }
+            }
+        }
+    }))
+}
+
+/// Copy the optional fields that are set into `target` under their common
+/// names. `logit_bias` is left to each backend since its wire format differs.
+fn insert_optional(target: &mut Value, params: &GenerationParams) {
+    if !params.stop.is_empty() {
+        target["stop"] = json!(params.stop);
+    }
+    if let Some(seed) = params.seed {
+        target["seed"] = json!(seed);
+    }
+    if let Some(penalty) = params.presence_penalty {
+        target["presence_penalty"] = json!(penalty);
+    }
+    if let Some(penalty) = params.frequency_penalty {
+        target["frequency_penalty"] = json!(penalty);
+    }
+}
+
+/// Usage from a response's prompt and completion count fields, when both
+/// are present.
+fn reported_usage(prompt_tokens: &Value, completion_tokens: &Value) -> Option<TokenUsage> {
+    Some(TokenUsage::reported(prompt_tokens.as_u64()?, completion_tokens.as_u64()?))
+}
+
+fn text_field(value: &Value, field: &str) -> Result<String, Box<dyn Error>> {
+    value
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| format!("Error: '{}' field not found in LLM response", field).into())
+}
+
+/// The original OptimaCore protocol: `{prompt, max_tokens, ...}` in,
+/// `{generated_text}` out.
+pub struct GenericBackend {
+    transport: Arc<HttpTransport>,
+}
+
+#[async_trait]
+impl LlmBackend for GenericBackend {
+    fn name(&self) -> &'static str {
+        "generic"
+    }
+
+    fn default_endpoint(&self) -> &'static str {
+        "http://localhost:8000/generate"
+    }
+
+    fn supported_params(&self) -> &'static [GenerationParam] {
+        &[GenerationParam::Stop]
+    }
+
+    async fn generate(&self, endpoint: &str, messages: &[ChatMessage], params: &GenerationParams) -> Result<Completion, Box<dyn Error>> {
+        let mut payload = json!({
+            "prompt": flatten_messages(messages),
+            "max_tokens": params.max_tokens,
+            "temperature": params.temperature,
+            "top_p": params.top_p,
+        });
+        insert_optional(&mut payload, params);
+        let response = post_json(&self.transport, endpoint, &payload, None).await?;
+        Ok(Completion {
+            text: text_field(&response["generated_text"], "generated_text")?,
+            usage: reported_usage(&response["usage"]["prompt_tokens"], &response["usage"]["completion_tokens"]),
+        })
+    }
+}
+
+/// OpenAI chat-completions API (`/v1/chat/completions`).
+pub struct OpenAiBackend {
+    transport: Arc<HttpTransport>,
+    model: String,
+    api_key: Option<String>,
+}
+
+#[async_trait]
+impl LlmBackend for OpenAiBackend {
+    fn name(&self) -> &'static str {
+        "openai"
+    }
+
+    fn default_endpoint(&self) -> &'static str {
+        "https://api.openai.com/v1/chat/completions"
+    }
+
+    fn model(&self) -> Option<&str> {
+        Some(&self.model)
+    }
+
+    fn supported_params(&self) -> &'static [GenerationParam] {
+        &[
+            GenerationParam::Stop,
+            GenerationParam::Seed,
+            GenerationParam::PresencePenalty,
+            GenerationParam::FrequencyPenalty,
+            GenerationParam::LogitBias,
+        ]
+    }
+
+    async fn generate(&self, endpoint: &str, messages: &[ChatMessage], params: &GenerationParams) -> Result<Completion, Box<dyn Error>> {
+        let payload = self.payload(messages, params, false);
+        let response = post_json(&self.transport, endpoint, &payload, self.api_key.as_deref()).await?;
+        Ok(Completion {
+            text: text_field(&response["choices"][0]["message"]["content"], "choices[0].message.content")?,
+            usage: reported_usage(&response["usage"]["prompt_tokens"], &response["usage"]["completion_tokens"]),
+        })
+    }
+
+    async fn generate_stream(&self, endpoint: &str, messages: &[ChatMessage], params: &GenerationParams) -> Result<TokenStream, Box<dyn Error>> {
+        let payload = self.payload(messages, params, true);
+        let response = self.transport.post_json(endpoint, &payload, self.api_key.as_deref(), true).await?;
+        Ok(token_stream(response, self.transport.read_timeout(), StreamFormat::Sse, |event| {
+            let choice = &event["choices"][0];
+            (choice["delta"]["content"].as_str().map(str::to_string), !choice["finish_reason"].is_null())
+        }))
+    }
+}
+
+impl OpenAiBackend {
+    fn payload(&self, messages: &[ChatMessage], params: &GenerationParams, stream: bool) -> Value {
+        let mut payload = json!({
+            "model": self.model,
+            "messages": messages,
+            "max_tokens": params.max_tokens,
+            "temperature": params.temperature,
+            "top_p": params.top_p,
+            "stream": stream,
+        });
+        insert_optional(&mut payload, params);
+        if !params.logit_bias.is_empty() {
+            payload["logit_bias"] = json!(params.logit_bias);
+        }
+        payload
+    }
+}
+
+/// Ollama `/api/generate`. Streamed replies are newline-delimited JSON.
+pub struct OllamaBackend {
+    transport: Arc<HttpTransport>,
+    model: String,
+}
+
+#[async_trait]
+impl LlmBackend for OllamaBackend {
+    fn name(&self) -> &'static str {
+        "ollama"
+    }
+
+    fn default_endpoint(&self) -> &'static str {
+        "http://localhost:11434/api/generate"
+    }
+
+    fn model(&self) -> Option<&str> {
+        Some(&self.model)
+    }
+
+    fn supported_params(&self) -> &'static [GenerationParam] {
+        &[
+            GenerationParam::Stop,
+            GenerationParam::Seed,
+            GenerationParam::PresencePenalty,
+            GenerationParam::FrequencyPenalty,
+        ]
+    }
+
+    async fn generate(&self, endpoint: &str, messages: &[ChatMessage], params: &GenerationParams) -> Result<Completion, Box<dyn Error>> {
+        let payload = self.payload(messages, params, false);
+        let response = post_json(&self.transport, endpoint, &payload, None).await?;
+        Ok(Completion {
+            text: text_field(&response["response"], "response")?,
+            usage: reported_usage(&response["prompt_eval_count"], &response["eval_count"]),
+        })
+    }
+
+    async fn generate_stream(&self, endpoint: &str, messages: &[ChatMessage], params: &GenerationParams) -> Result<TokenStream, Box<dyn Error>> {
+        let payload = self.payload(messages, params, true);
+        let response = self.transport.post_json(endpoint, &payload, None, true).await?;
+        Ok(token_stream(response, self.transport.read_timeout(), StreamFormat::NdJson, |event| {
+            (event["response"].as_str().map(str::to_string), event["done"].as_bool().unwrap_or(false))
+        }))
+    }
+}
+
+impl OllamaBackend {
+    fn payload(&self, messages: &[ChatMessage], params: &GenerationParams, stream: bool) -> Value {
+        let (system, rest): (Vec<ChatMessage>, Vec<ChatMessage>) =
+            messages.iter().cloned().partition(|message| message.role == Role::System);
+        let mut payload = json!({
+            "model": self.model,
+            "prompt": flatten_messages(&rest),
+            "stream": stream,
+            "options": {
+                "num_predict": params.max_tokens,
+                "temperature": params.temperature,
+                "top_p": params.top_p,
+            },
+        });
+        insert_optional(&mut payload["options"], params);
+        if !system.is_empty() {
+            payload["system"] = json!(flatten_messages(&system));
+        }
+        payload
+    }
+}
+
+/// llama.cpp server `/completion`.
+pub struct LlamaCppBackend {
+    transport: Arc<HttpTransport>,
+}
+
+#[async_trait]
+impl LlmBackend for LlamaCppBackend {
+    fn name(&self) -> &'static str {
+        "llamacpp"
+    }
+
+    fn default_endpoint(&self) -> &'static str {
+        "http://localhost:8080/completion"
+    }
+
+    fn supported_params(&self) -> &'static [GenerationParam] {
+        &[
+            GenerationParam::Stop,
+            GenerationParam::Seed,
+            GenerationParam::PresencePenalty,
+            GenerationParam::FrequencyPenalty,
+            GenerationParam::LogitBias,
+        ]
+    }
+
+    async fn generate(&self, endpoint: &str, messages: &[ChatMessage], params: &GenerationParams) -> Result<Completion, Box<dyn Error>> {
+        let payload = Self::payload(messages, params, false);
+        let response = post_json(&self.transport, endpoint, &payload, None).await?;
+        Ok(Completion {
+            text: text_field(&response["content"], "content")?,
+            usage: reported_usage(&response["tokens_evaluated"], &response["tokens_predicted"]),
+        })
+    }
+
+    async fn generate_stream(&self, endpoint: &str, messages: &[ChatMessage], params: &GenerationParams) -> Result<TokenStream, Box<dyn Error>> {
+        let payload = Self::payload(messages, params, true);
+        let response = self.transport.post_json(endpoint, &payload, None, true).await?;
+        Ok(token_stream(response, self.transport.read_timeout(), StreamFormat::Sse, |event| {
+            (event["content"].as_str().map(str::to_string), event["stop"].as_bool().unwrap_or(false))
+        }))
+    }
+}
+
+impl LlamaCppBackend {
+    fn payload(messages: &[ChatMessage], params: &GenerationParams, stream: bool) -> Value {
+        let mut payload = json!({
+            "prompt": flatten_messages(messages),
+            "n_predict": params.max_tokens,
+            "temperature": params.temperature,
+            "top_p": params.top_p,
+            "stream": stream,
+        });
+        insert_optional(&mut payload, params);
+        if !params.logit_bias.is_empty() {
+            // llama.cpp takes `[[token, bias], ...]` with numeric ids or token text.
+            let pairs: Vec<Value> = params
+                .logit_bias
+                .iter()
+                .map(|(token, bias)| match token.parse::<i64>() {
+                    Ok(id) => json!([id, bias]),
+                    Err(_) => json!([token, bias]),
+                })
+                .collect();
+            payload["logit_bias"] = json!(pairs);
+        }
+        payload
+    }
+}
+
+/// Hugging Face text-generation-inference `/generate`.
+pub struct TgiBackend {
+    transport: Arc<HttpTransport>,
+}
+
+#[async_trait]
+impl LlmBackend for TgiBackend {
+    fn name(&self) -> &'static str {
+        "tgi"
+    }
+
+    fn default_endpoint(&self) -> &'static str {
+        "http://localhost:8080/generate"
+    }
+
+    fn supported_params(&self) -> &'static [GenerationParam] {
+        &[GenerationParam::Stop, GenerationParam::Seed]
+    }
+
+    async fn generate(&self, endpoint: &str, messages: &[ChatMessage], params: &GenerationParams) -> Result<Completion, Box<dyn Error>> {
+        let payload = Self::payload(messages, params);
+        let response = post_json(&self.transport, endpoint, &payload, None).await?;
+        // TGI answers with an object, or a one-element array on some versions.
+        let body = if response.is_array() { &response[0] } else { &response };
+        // TGI reports generated tokens only; the prompt side is estimated.
+        let usage = body["details"]["generated_tokens"].as_u64().map(|completion_tokens| TokenUsage {
+            prompt_tokens: estimate_tokens(&flatten_messages(messages)),
+            completion_tokens,
+            estimated: true,
+        });
+        Ok(Completion { text: text_field(&body["generated_text"], "generated_text")?, usage })
+    }
+
+    /// Streams from the sibling `/generate_stream` route.
+    async fn generate_stream(&self, endpoint: &str, messages: &[ChatMessage], params: &GenerationParams) -> Result<TokenStream, Box<dyn Error>> {
+        let payload = Self::payload(messages, params);
+        let stream_endpoint = match endpoint.strip_suffix("/generate") {
+            Some(base) => format!("{}/generate_stream", base),
+            None => endpoint.to_string(),
+        };
+        let response = self.transport.post_json(&stream_endpoint, &payload, None, true).await?;
+        Ok(token_stream(response, self.transport.read_timeout(), StreamFormat::Sse, |event| {
+            let special = event["token"]["special"].as_bool().unwrap_or(false);
+            let text = if special { None } else { event["token"]["text"].as_str().map(str::to_string) };
+            (text, !event["generated_text"].is_null())
+        }))
+    }
+}
+
+impl TgiBackend {
+    fn payload(messages: &[ChatMessage], params: &GenerationParams) -> Value {
+        let mut payload = json!({
+            "inputs": flatten_messages(messages),
+            "parameters": {
+                "max_new_tokens": params.max_tokens,
+                "temperature": params.temperature,
+                "top_p": params.top_p,
+                "details": true,
+            },
+        });
+        insert_optional(&mut payload["parameters"], params);
+        payload
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resilience::RetryPolicy;
+    use reqwest::Client;
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn transport() -> Arc<HttpTransport> {
+        let retry = RetryPolicy { max_retries: 0, base_delay: Duration::ZERO, max_delay: Duration::ZERO };
+        Arc::new(HttpTransport::new(Client::new(), retry, Duration::from_secs(5), 5, Duration::from_secs(30)))
+    }
+
+    fn messages() -> Vec<ChatMessage> {
+        vec![ChatMessage::system("Be brief."), ChatMessage::user("Hi")]
+    }
+
+    fn params() -> GenerationParams {
+        GenerationParams { stop: vec!["\n\n".to_string()], seed: Some(7), ..GenerationParams::default() }
+    }
+
+    async fn mock(server: &MockServer, route: &str, response: ResponseTemplate) {
+        Mock::given(method("POST")).and(path(route)).respond_with(response).expect(1).mount(server).await;
+    }
+
+    async fn sent_body(server: &MockServer) -> Value {
+        let requests = server.received_requests().await.unwrap();
+        requests.last().unwrap().body_json().unwrap()
+    }
+
+    async fn collect(tokens: TokenStream) -> Vec<String> {
+        tokens.map(|chunk| chunk.unwrap()).collect().await
+    }
+
+    fn sse(events: &[Value]) -> ResponseTemplate {
+        let body: String = events.iter().map(|event| format!("data: {}\n\n", event)).collect();
+        ResponseTemplate::new(200).set_body_raw(body, "text/event-stream")
+    }
+
+    #[tokio::test]
+    async fn openai_payload_completion_and_sse_stream() {
+        let server = MockServer::start().await;
+        let backend = OpenAiBackend { transport: transport(), model: "gpt-test".to_string(), api_key: Some("sk-test".to_string()) };
+        let endpoint = format!("{}/v1/chat/completions", server.uri());
+
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .and(header("authorization", "Bearer sk-test"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "choices": [{"message": {"role": "assistant", "content": "Hello"}, "finish_reason": "stop"}],
+                "usage": {"prompt_tokens": 12, "completion_tokens": 3},
+            })))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        let completion = backend.generate(&endpoint, &messages(), &params()).await.unwrap();
+        assert_eq!(completion.text, "Hello");
+        assert_eq!(completion.usage, Some(TokenUsage::reported(12, 3)));
+
+        let body = sent_body(&server).await;
+        assert_eq!(body["model"], "gpt-test");
+        assert_eq!(body["messages"], json!([{"role": "system", "content": "Be brief."}, {"role": "user", "content": "Hi"}]));
+        assert_eq!(body["max_tokens"], 256);
+        assert_eq!(body["stop"], json!(["\n\n"]));
+        assert_eq!(body["seed"], 7);
+        assert_eq!(body["stream"], false);
+
+        server.reset().await;
+        mock(
+            &server,
+            "/v1/chat/completions",
+            sse(&[
+                json!({"choices": [{"delta": {"role": "assistant"}, "finish_reason": null}]}),
+                json!({"choices": [{"delta": {"content": "Hel"}, "finish_reason": null}]}),
+                json!({"choices": [{"delta": {"content": "lo"}, "finish_reason": null}]}),
+                json!({"choices": [{"delta": {}, "finish_reason": "stop"}]}),
+            ]),
+        )
+        .await;
+        let tokens = backend.generate_stream(&endpoint, &messages(), &params()).await.unwrap();
+        assert_eq!(collect(tokens).await, ["Hel", "lo"]);
+        assert_eq!(sent_body(&server).await["stream"], true);
+    }
+
+    #[tokio::test]
+    async fn ollama_payload_completion_and_ndjson_stream() {
+        let server = MockServer::start().await;
+        let backend = OllamaBackend { transport: transport(), model: "llama-test".to_string() };
+        let endpoint = format!("{}/api/generate", server.uri());
+
+        mock(
+            &server,
+            "/api/generate",
+            ResponseTemplate::new(200).set_body_json(json!({
+                "response": "Hello",
+                "done": true,
+                "prompt_eval_count": 12,
+                "eval_count": 3,
+            })),
+        )
+        .await;
+        let completion = backend.generate(&endpoint, &messages(), &params()).await.unwrap();
+        assert_eq!(completion.text, "Hello");
+        assert_eq!(completion.usage, Some(TokenUsage::reported(12, 3)));
+
+        let body = sent_body(&server).await;
+        assert_eq!(body["model"], "llama-test");
+        assert_eq!(body["system"], "Be brief.");
+        assert_eq!(body["prompt"], "Hi");
+        assert_eq!(body["stream"], false);
+        assert_eq!(body["options"]["num_predict"], 256);
+        assert_eq!(body["options"]["seed"], 7);
+        assert_eq!(body["options"]["stop"], json!(["\n\n"]));
+
+        server.reset().await;
+        let ndjson = [
+            json!({"response": "Hel", "done": false}),
+            json!({"response": "lo", "done": false}),
+            json!({"response": "", "done": true, "eval_count": 2}),
+        ]
+        .iter()
+        .map(|event| format!("{}\n", event))
+        .collect::<String>();
+        mock(&server, "/api/generate", ResponseTemplate::new(200).set_body_raw(ndjson, "application/x-ndjson")).await;
+        let tokens = backend.generate_stream(&endpoint, &messages(), &params()).await.unwrap();
+        assert_eq!(collect(tokens).await, ["Hel", "lo"]);
+        assert_eq!(sent_body(&server).await["stream"], true);
+    }
+
+    #[tokio::test]
+    async fn llamacpp_payload_completion_and_sse_stream() {
+        let server = MockServer::start().await;
+        let backend = LlamaCppBackend { transport: transport() };
+        let endpoint = format!("{}/completion", server.uri());
+
+        mock(
+            &server,
+            "/completion",
+            ResponseTemplate::new(200).set_body_json(json!({
+                "content": "Hello",
+                "stop": true,
+                "tokens_evaluated": 12,
+                "tokens_predicted": 3,
+            })),
+        )
+        .await;
+        let mut params = params();
+        params.logit_bias.insert("15043".to_string(), -1.0);
+        let completion = backend.generate(&endpoint, &messages(), &params).await.unwrap();
+        assert_eq!(completion.text, "Hello");
+        assert_eq!(completion.usage, Some(TokenUsage::reported(12, 3)));
+
+        let body = sent_body(&server).await;
+        assert_eq!(body["prompt"], "Be brief.\n\nHi");
+        assert_eq!(body["n_predict"], 256);
+        assert_eq!(body["seed"], 7);
+        assert_eq!(body["logit_bias"], json!([[15043, -1.0]]));
+        assert_eq!(body["stream"], false);
+
+        server.reset().await;
+        mock(
+            &server,
+            "/completion",
+            sse(&[
+                json!({"content": "Hel", "stop": false}),
+                json!({"content": "lo", "stop": false}),
+                json!({"content": "", "stop": true, "tokens_predicted": 2}),
+            ]),
+        )
+        .await;
+        let tokens = backend.generate_stream(&endpoint, &messages(), &params).await.unwrap();
+        assert_eq!(collect(tokens).await, ["Hel", "lo"]);
+        assert_eq!(sent_body(&server).await["stream"], true);
+    }
+
+    #[tokio::test]
+    async fn tgi_payload_completion_and_sse_stream() {
+        let server = MockServer::start().await;
+        let backend = TgiBackend { transport: transport() };
+        let endpoint = format!("{}/generate", server.uri());
+
+        // Some TGI versions wrap the reply in a one-element array.
+        mock(
+            &server,
+            "/generate",
+            ResponseTemplate::new(200).set_body_json(json!([{
+                "generated_text": "Hello",
+                "details": {"finish_reason": "eos_token", "generated_tokens": 3},
+            }])),
+        )
+        .await;
+        let completion = backend.generate(&endpoint, &messages(), &params()).await.unwrap();
+        assert_eq!(completion.text, "Hello");
+        let usage = completion.usage.unwrap();
+        assert_eq!(usage.completion_tokens, 3);
+        assert_eq!(usage.prompt_tokens, estimate_tokens("Be brief.\n\nHi"));
+        assert!(usage.estimated);
+
+        let body = sent_body(&server).await;
+        assert_eq!(body["inputs"], "Be brief.\n\nHi");
+        assert_eq!(body["parameters"]["max_new_tokens"], 256);
+        assert_eq!(body["parameters"]["details"], true);
+        assert_eq!(body["parameters"]["seed"], 7);
+        assert_eq!(body["parameters"]["stop"], json!(["\n\n"]));
+
+        server.reset().await;
+        mock(
+            &server,
+            "/generate_stream",
+            sse(&[
+                json!({"token": {"id": 1, "text": "Hel", "special": false}, "generated_text": null}),
+                json!({"token": {"id": 2, "text": "lo", "special": false}, "generated_text": null}),
+                json!({"token": {"id": 3, "text": "</s>", "special": true}, "generated_text": "Hello"}),
+            ]),
+        )
+        .await;
+        let tokens = backend.generate_stream(&endpoint, &messages(), &params()).await.unwrap();
+        assert_eq!(collect(tokens).await, ["Hel", "lo"]);
+    }
+
+    #[test]
+    fn ollama_rejects_logit_bias() {
+        let backend = OllamaBackend { transport: transport(), model: "llama3".to_string() };
+        let biased = GenerationParams { logit_bias: [("42".to_string(), 5.0)].into(), ..params() };
+        let error = biased.validate(backend.name(), backend.supported_params()).unwrap_err();
+        assert_eq!(error.to_string(), "the ollama backend does not support generation parameter `logit_bias`");
+        assert!(params().validate(backend.name(), backend.supported_params()).is_ok());
+    }
+}
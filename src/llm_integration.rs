@@ -1,59 +1,277 @@
-use serde_json::json;
-use reqwest::Client;
-use tracing::info;
+use futures_util::StreamExt;
+use tracing::{info, warn};
 use std::error::Error;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::endpoint_pool::EndpointPool;
+use crate::generation::GenerationParams;
+use crate::llm_backend::{backend_from_env, LlmBackend, TokenStream};
+use crate::prompt::{flatten_messages, ChatMessage, PromptBuilder};
+use crate::resilience::{is_endpoint_failure, HttpTransport};
+use crate::usage::{ModelPrice, PriceTable, TokenUsage};
 
 pub struct LLMClient {
-    client: Client,
-    api_endpoint: String,
+    backend: Box<dyn LlmBackend>,
+    prompt_builder: PromptBuilder,
+    transport: Arc<HttpTransport>,
+    pool: Arc<EndpointPool>,
+    default_params: GenerationParams,
+    price: Option<ModelPrice>,
 }
 
 impl LLMClient {
     pub async fn new() -> Result<Self, Box<dyn Error>> {
-        let client = Client::new();
-        let api_endpoint = std::env::var("LLM_API_ENDPOINT")
-            .unwrap_or_else(|_| "http://localhost:8000/generate".to_string());
-        
-        info!("LLMClient initialized. Target API: {}", api_endpoint);
-        Ok(Self { client, api_endpoint })
-    }
-    
-    pub async fn generate(&self, prompt: &str, context: &[String]) -> Result<String, Box<dyn Error>> {
-        let context_str = if context.is_empty() {
-            String::new()
-        } else {
-            format!("Context from Knowledge Folder: {}\n\n", context.join("\n"))
-        };
-        
-        let full_prompt = format!("{}{}", context_str, prompt);
-        
-        let payload = json!({
-            "prompt": full_prompt,
-            "max_tokens": 256,
-            "temperature": 0.7,
-            "top_p": 0.9,
-        });
-        
-        info!("Sending request to LLM API: {}", self.api_endpoint);
-        
-        let response = self.client
-            .post(&self.api_endpoint)
-            .json(&payload)
-            .send()
-            .await?;
-        
-        if !response.status().is_success() {
-            let error_text = response.text().await.unwrap_or_default();
-            return Err(format!("LLM API request failed: {} - {}", response.status(), error_text).into());
-        }
-        
-        let response_json: serde_json::Value = response.json().await?;
-        let generated_text = response_json["generated_text"]
-            .as_str()
-            .ok_or_else(|| "Error: 'generated_text' field not found in LLM response".to_string())?;
-        
-        info!("Received LLM response (partial): {}", &generated_text[..std::cmp::min(generated_text.len(), 100)]);
-        
-        Ok(generated_text.to_string())
-    }
-}
\ No newline at end of file
+        Self::for_tier(None).await
+    }
+
+    /// Client for one cascade tier, configured from `LLM_<TIER>_*` variables
+    /// where set and the shared `LLM_*` ones otherwise.
+    pub async fn for_tier(tier: Option<&str>) -> Result<Self, Box<dyn Error>> {
+        let transport = Arc::new(HttpTransport::from_env()?);
+        let backend = backend_from_env(transport.clone(), tier)?;
+        let pool = Arc::new(EndpointPool::from_env(backend.default_endpoint(), tier));
+        if pool.is_empty() {
+            return Err("No LLM endpoints configured".into());
+        }
+
+        let health_secs: u64 = std::env::var("LLM_HEALTH_CHECK_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(15);
+        if pool.len() > 1 && health_secs > 0 {
+            tokio::spawn(pool.clone().run_health_checks(transport.clone(), Duration::from_secs(health_secs)));
+        }
+
+        let client = Self::with_backend(backend, PromptBuilder::new(), transport, pool);
+        client.validate_params(&client.default_params)?;
+        info!("LLMClient initialized. Backend: {}, {} endpoint(s)", client.backend.name(), client.pool.len());
+        Ok(client)
+    }
+
+    /// Generation defaults and the price of the backend's model come from
+    /// the environment; see `GenerationParams::from_env` and
+    /// `PriceTable::from_env`.
+    pub fn with_backend(backend: Box<dyn LlmBackend>, prompt_builder: PromptBuilder, transport: Arc<HttpTransport>, pool: Arc<EndpointPool>) -> Self {
+        let price = PriceTable::from_env().lookup(backend.model().unwrap_or(backend.name()));
+        Self { backend, prompt_builder, transport, pool, default_params: GenerationParams::from_env(), price }
+    }
+
+    /// Price of this client's model, or `None` if the price table has no
+    /// entry for it.
+    pub fn price(&self) -> Option<ModelPrice> {
+        self.price
+    }
+
+    pub fn with_default_params(mut self, default_params: GenerationParams) -> Self {
+        self.default_params = default_params;
+        self
+    }
+
+    pub fn default_params(&self) -> &GenerationParams {
+        &self.default_params
+    }
+
+    /// Reject params the configured backend cannot honour instead of letting
+    /// the server silently ignore them.
+    pub fn validate_params(&self, params: &GenerationParams) -> Result<(), Box<dyn Error>> {
+        params.validate(self.backend.name(), self.backend.supported_params())?;
+        Ok(())
+    }
+
+    pub fn backend(&self) -> &dyn LlmBackend {
+        self.backend.as_ref()
+    }
+
+    pub fn pool(&self) -> Arc<EndpointPool> {
+        self.pool.clone()
+    }
+
+    pub fn transport(&self) -> Arc<HttpTransport> {
+        self.transport.clone()
+    }
+
+    /// Assemble the messages that `generate` sends to the backend.
+    pub fn build_messages(&self, prompt: &str, context: &[String]) -> Vec<ChatMessage> {
+        self.prompt_builder.build(prompt, context)
+    }
+
+    /// The assembled messages rendered as a single prompt string.
+    pub fn build_prompt(&self, prompt: &str, context: &[String]) -> String {
+        flatten_messages(&self.build_messages(prompt, context))
+    }
+
+    /// Generate a response and its token usage, estimated locally when the
+    /// backend does not report it. Only endpoint failures (see
+    /// `is_endpoint_failure`) move on to the next endpoint; any other error,
+    /// such as a 4xx for bad params, is returned as is.
+    pub async fn generate(&self, prompt: &str, context: &[String], params: &GenerationParams) -> Result<(String, TokenUsage), Box<dyn Error>> {
+        self.validate_params(params)?;
+        let messages = self.build_messages(prompt, context);
+
+        let mut last_error = String::from("No LLM endpoints available");
+        for endpoint in self.pool.candidates(&self.transport) {
+            let _in_flight = endpoint.begin();
+            let started = Instant::now();
+            match self.backend.generate(endpoint.url(), &messages, params).await {
+                Ok(completion) => {
+                    endpoint.record(started.elapsed(), true);
+                    let generated_text = completion.text;
+                    info!("Received LLM response (partial): {}", generated_text.chars().take(100).collect::<String>());
+                    let usage = completion
+                        .usage
+                        .unwrap_or_else(|| TokenUsage::estimate(&flatten_messages(&messages), &generated_text));
+                    return Ok((generated_text, usage));
+                }
+                Err(e) if is_endpoint_failure(&*e) => {
+                    endpoint.record(started.elapsed(), false);
+                    warn!("LLM endpoint {} failed ({}); failing over.", endpoint.url(), e);
+                    last_error = format!("{}: {}", endpoint.url(), e);
+                }
+                Err(e) => {
+                    endpoint.record_rejected(started.elapsed());
+                    return Err(e);
+                }
+            }
+        }
+        Err(format!("All LLM endpoints failed. Last error: {}", last_error).into())
+    }
+
+    /// Open a streaming request, failing over between endpoints until one
+    /// accepts it. The endpoint counts as busy until the stream is dropped.
+    pub async fn generate_stream(&self, prompt: &str, context: &[String], params: &GenerationParams) -> Result<TokenStream, Box<dyn Error>> {
+        self.validate_params(params)?;
+        let messages = self.build_messages(prompt, context);
+
+        let mut last_error = String::from("No LLM endpoints available");
+        for endpoint in self.pool.candidates(&self.transport) {
+            let in_flight = endpoint.begin();
+            let started = Instant::now();
+            info!("Opening streaming request to {} backend at {}", self.backend.name(), endpoint.url());
+            match self.backend.generate_stream(endpoint.url(), &messages, params).await {
+                Ok(tokens) => {
+                    endpoint.record(started.elapsed(), true);
+                    let tokens = tokens.map(move |chunk| {
+                        let _ = &in_flight;
+                        chunk
+                    });
+                    return Ok(Box::pin(tokens));
+                }
+                Err(e) if is_endpoint_failure(&*e) => {
+                    endpoint.record(started.elapsed(), false);
+                    warn!("LLM endpoint {} failed ({}); failing over.", endpoint.url(), e);
+                    last_error = format!("{}: {}", endpoint.url(), e);
+                }
+                Err(e) => {
+                    endpoint.record_rejected(started.elapsed());
+                    return Err(e);
+                }
+            }
+        }
+        Err(format!("All LLM endpoints failed. Last error: {}", last_error).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generation::GenerationParam;
+    use crate::llm_backend::Completion;
+    use crate::resilience::RetryPolicy;
+    use async_trait::async_trait;
+    use reqwest::Client;
+    use serde_json::{json, Value};
+    use wiremock::matchers::method;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    /// Posts to the endpoint and answers with the `text` field of the reply,
+    /// and `usage` as `[prompt, completion]` when present.
+    struct Echo {
+        transport: Arc<HttpTransport>,
+    }
+
+    #[async_trait]
+    impl LlmBackend for Echo {
+        fn name(&self) -> &'static str {
+            "echo"
+        }
+
+        fn default_endpoint(&self) -> &'static str {
+            "http://localhost:8000"
+        }
+
+        fn supported_params(&self) -> &'static [GenerationParam] {
+            &[]
+        }
+
+        async fn generate(&self, endpoint: &str, _messages: &[ChatMessage], _params: &GenerationParams) -> Result<Completion, Box<dyn Error>> {
+            let response = self.transport.post_json(endpoint, &json!({}), None, false).await?;
+            let response: Value = response.json().await?;
+            let usage = response["usage"].as_array().map(|usage| TokenUsage::reported(usage[0].as_u64().unwrap_or(0), usage[1].as_u64().unwrap_or(0)));
+            Ok(Completion { text: response["text"].as_str().unwrap_or_default().to_string(), usage })
+        }
+    }
+
+    async fn server(response: ResponseTemplate) -> MockServer {
+        let server = MockServer::start().await;
+        Mock::given(method("POST")).respond_with(response).mount(&server).await;
+        server
+    }
+
+    fn client(first: &MockServer, second: &MockServer) -> LLMClient {
+        let retry = RetryPolicy { max_retries: 0, base_delay: Duration::ZERO, max_delay: Duration::ZERO };
+        let transport = Arc::new(HttpTransport::new(Client::new(), retry, Duration::from_secs(5), 5, Duration::from_secs(30)));
+        // The first endpoint carries more weight, so it is tried first.
+        let pool = Arc::new(EndpointPool::parse(&format!("{}|2,{}", first.uri(), second.uri())));
+        LLMClient::with_backend(Box::new(Echo { transport: transport.clone() }), PromptBuilder::new(), transport, pool)
+    }
+
+    async fn hits(server: &MockServer) -> usize {
+        server.received_requests().await.unwrap().len()
+    }
+
+    #[tokio::test]
+    async fn server_errors_fail_over() {
+        let down = server(ResponseTemplate::new(503)).await;
+        let up = server(ResponseTemplate::new(200).set_body_json(json!({"text": "ok"}))).await;
+        let client = client(&down, &up);
+
+        let (text, _) = client.generate("Hi", &[], &GenerationParams::default()).await.unwrap();
+        assert_eq!(text, "ok");
+        assert_eq!(client.pool().available(&client.transport()), 1);
+    }
+
+    #[tokio::test]
+    async fn client_errors_are_returned_without_failing_over() {
+        let rejecting = server(ResponseTemplate::new(400).set_body_string("context too long")).await;
+        let other = server(ResponseTemplate::new(200).set_body_json(json!({"text": "ok"}))).await;
+        let client = client(&rejecting, &other);
+
+        let error = client.generate("Hi", &[], &GenerationParams::default()).await.unwrap_err();
+        assert!(error.to_string().contains("context too long"), "{}", error);
+        assert_eq!(hits(&other).await, 0);
+        assert_eq!(client.pool().available(&client.transport()), 2);
+    }
+
+    #[tokio::test]
+    async fn log_preview_respects_char_boundaries() {
+        let text = "€".repeat(50);
+        let server = server(ResponseTemplate::new(200).set_body_json(json!({"text": text}))).await;
+        let client = client(&server, &server);
+        assert_eq!(client.generate("Hi", &[], &GenerationParams::default()).await.unwrap().0, text);
+    }
+
+    #[tokio::test]
+    async fn usage_is_estimated_when_the_backend_reports_none() {
+        let reporting = server(ResponseTemplate::new(200).set_body_json(json!({"text": "Hello there", "usage": [12, 3]}))).await;
+        let (_, usage) = client(&reporting, &reporting).generate("Hi", &[], &GenerationParams::default()).await.unwrap();
+        assert_eq!(usage, TokenUsage::reported(12, 3));
+
+        let silent = server(ResponseTemplate::new(200).set_body_json(json!({"text": "Hello there"}))).await;
+        let client = client(&silent, &silent);
+        let (_, usage) = client.generate("Hi", &[], &GenerationParams::default()).await.unwrap();
+        assert!(usage.estimated);
+        assert_eq!(usage.completion_tokens, 4);
+        assert_eq!(usage.prompt_tokens, crate::usage::estimate_tokens(&client.build_prompt("Hi", &[])));
+    }
+}
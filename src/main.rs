@@ -1,31 +1,295 @@
 use optimacore::core::{OptimaCore, ProcessedResponse};
+use optimacore::generation::GenerationParams;
+use optimacore::request::RequestOptions;
+use optimacore::rules::RuleSet;
+use optimacore::stats::{format_bucket, StatsCounters, StatsStore};
+use optimacore::streaming::StreamEvent;
+use optimacore::structured::OutputSchema;
+use optimacore::verifier::{ClaimVerdict, CONTRADICTION_THRESHOLD};
+use std::io::Write;
 use std::env;
 use std::path::Path;
-use tracing::{info, Level};
-use tracing_subscriber;
+use std::time::Duration;
+use tracing::{info, warn, Level};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     tracing_subscriber::fmt().with_max_level(Level::INFO).init();
-    optimacore::ffi::init_julia();
 
-    let prompt = env::args().skip(1).collect::<Vec<String>>().join(" ");
+    if env::args().nth(1).as_deref() == Some("stats") {
+        return print_stats();
+    }
+    if env::args().nth(1).as_deref() == Some("verifier") {
+        return verifier_command(env::args().skip(2));
+    }
+
+    #[cfg(feature = "julia")]
+    if let Err(e) = optimacore::ffi::init_julia() {
+        warn!("{}", e);
+    }
+
+    let mut explain = false;
+    let mut stream = false;
+    let mut options = RequestOptions::default();
+    let mut params: Option<GenerationParams> = None;
+    let mut schema: Option<OutputSchema> = None;
+    let mut words = Vec::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--explain" => explain = true,
+            "--stream" => stream = true,
+            "--timeout-ms" => {
+                let millis: u64 = args.next().ok_or("--timeout-ms requires a value")?.parse()?;
+                options = RequestOptions::with_timeout(Duration::from_millis(millis));
+            }
+            "--max-tokens" => {
+                params.get_or_insert_with(GenerationParams::from_env).max_tokens =
+                    args.next().ok_or("--max-tokens requires a value")?.parse()?;
+            }
+            "--temperature" => {
+                params.get_or_insert_with(GenerationParams::from_env).temperature =
+                    args.next().ok_or("--temperature requires a value")?.parse()?;
+            }
+            "--seed" => {
+                params.get_or_insert_with(GenerationParams::from_env).seed =
+                    Some(args.next().ok_or("--seed requires a value")?.parse()?);
+            }
+            "--schema" => {
+                let path = args.next().ok_or("--schema requires a file")?;
+                schema = Some(OutputSchema::new(serde_json::from_str(&std::fs::read_to_string(path)?)?)?);
+            }
+            _ => words.push(arg),
+        }
+    }
+    options.params = params;
+    if let Some(schema) = schema {
+        options = options.with_schema(schema);
+    }
+
+    let prompt = words.join(" ");
     if prompt.is_empty() {
-        eprintln!("Usage: optimacore <prompt>");
+        eprintln!("Usage: optimacore [--explain] [--stream] [--timeout-ms <ms>] [--max-tokens <n>] [--temperature <t>] [--seed <n>] [--schema <file>] <prompt>\n       optimacore stats\n       optimacore verifier test-rules [--rules <dir>] [--domain <name>]... [--output <text> --fact <text>...]");
         return Ok(());
     }
 
     let ekf_path = Path::new("./ekf_storage");
     let mut core = OptimaCore::new(ekf_path).await?;
 
-    let response: ProcessedResponse = core.process_request(&prompt).await?;
-    println!("{}", response.output);
+    if explain {
+        let report = core.explain(&prompt).await?;
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    if stream {
+        let mut events = match core.process_request_stream(&prompt, &options).await {
+            Ok(events) => events,
+            Err(e) => {
+                // A blocked prompt is still counted.
+                core.flush_stats()?;
+                return Err(e);
+            }
+        };
+        while let Some(event) = events.recv().await {
+            match event {
+                StreamEvent::Chunk(chunk) => {
+                    print!("{}", chunk);
+                    std::io::stdout().flush()?;
+                }
+                StreamEvent::Aborted { contradiction_score, .. } => {
+                    println!();
+                    warn!("Stream aborted: contradiction detected (score: {:.2})", contradiction_score);
+                }
+                StreamEvent::Failed(e) => {
+                    println!();
+                    warn!("Stream failed: {}", e);
+                }
+                StreamEvent::Done { verified, .. } => {
+                    println!();
+                    info!("Verified: {}", verified);
+                }
+            }
+        }
+        core.flush_stats()?;
+        return Ok(());
+    }
+
+    let response: ProcessedResponse = match core.process_request_with(&prompt, &options).await {
+        Ok(response) => response,
+        Err(e) => {
+            core.flush_stats()?;
+            return Err(e);
+        }
+    };
+    match &response.structured {
+        Some(value) => println!("{}", serde_json::to_string_pretty(value)?),
+        None => println!("{}", response.output),
+    }
     info!("Tokens Saved: {:.2}%", (1.0 - response.compression_ratio) * 100.0);
     info!("EKF Knowledge Used: {}", if response.ekf_knowledge.is_empty() { "No" } else { "Yes" });
     info!("Reflection Trimmed: {}", response.reflection_trimmed);
     info!("Bandwidth Saved: {:.2} GB/s", response.bandwidth_saved);
     info!("GPU Utilization: {:.2}%", response.gpu_utilization);
+    info!("Protections Applied: {:?}", response.protections);
+    info!("Served From Cache: {}", response.cache_hit);
+    info!(
+        "Tokens: {} prompt / {} completion{}, Cost: ${:.6} (${:.6} without OptimaCore)",
+        response.cost.usage.prompt_tokens,
+        response.cost.usage.completion_tokens,
+        if response.cost.usage.estimated { " (estimated)" } else { "" },
+        response.cost.cost_usd,
+        response.cost.baseline_cost_usd
+    );
+    if let Some(attempt) = response.passed_attempt.filter(|attempt| *attempt > 0) {
+        info!("Passed Verification On Attempt: {} of {}", attempt + 1, response.attempts.len());
+    }
+    if let Some(report) = &response.verification {
+        let count = |verdict| report.claims.iter().filter(|claim| claim.verdict == verdict).count();
+        info!(
+            "Claims: {} supported, {} contradicted, {} unknown",
+            count(ClaimVerdict::Supported),
+            count(ClaimVerdict::Contradicted),
+            count(ClaimVerdict::Unknown)
+        );
+        for claim in report.contradicted() {
+            warn!("Contradicted claim: {:?} (fact {})", claim.text, claim.fact_key.as_deref().unwrap_or("?"));
+        }
+    }
+    if let Some(found) = &response.output_loop {
+        info!("Output Loop Truncated: {}", found.reason);
+    }
+    for redaction in &response.pii {
+        info!("PII Redacted: {} as {} ({} occurrence(s))", redaction.kind, redaction.placeholder, redaction.occurrences);
+    }
+    for decision in &response.policy {
+        warn!("Content Policy: {:?} {:?} by {} ({}, {} match(es))", decision.action, decision.stage, decision.rule, decision.category, decision.matches);
+    }
+    if let Some(tier) = &response.answered_by {
+        info!("Answered By: {} ({} escalation(s))", tier, response.escalations);
+    }
+    if !response.stages_cut.is_empty() {
+        info!("Stages Cut: {}", response.stages_cut.join(", "));
+    }
+    core.flush_stats()?;
 
     Ok(())
 }
 
+fn print_stats() -> Result<(), Box<dyn std::error::Error>> {
+    let snapshot = StatsStore::load_merged()?;
+    let totals = snapshot.totals.summary();
+    println!("Total Requests: {}", totals.total_requests);
+    println!("Avg Tokens Saved: {:.2}%", totals.avg_compression_ratio * 100.0);
+    println!("Reflections Trimmed: {}", totals.reflections_trimmed);
+    println!("Avg GPU Utilization: {:.2}%", totals.avg_gpu_utilization);
+    println!("Total Bandwidth Saved: {:.2} GB/s", totals.total_bandwidth_saved);
+    println!("Response Cache Hits: {}", totals.cache_hits);
+    println!("Cascade Escalations: {}", totals.escalations);
+    println!("Total LLM Cost: ${:.4}", totals.total_cost_usd);
+    println!("Total Saved: ${:.4}", totals.total_saved_usd);
+    for (tier, answers) in &totals.answers_by_tier {
+        println!("  Answered by {}: {}", tier, answers);
+    }
+    println!(
+        "Content Policy: {} blocked, {} redacted, {} flagged",
+        totals.policy_blocks, totals.policy_redactions, totals.policy_flags
+    );
+    for (category, count) in &totals.policy_by_category {
+        println!("  {}: {}", category, count);
+    }
+    if totals.shadow_samples > 0 {
+        println!(
+            "Shadow Comparisons: {} (avg similarity {:.2}, avg quality delta {:+.3}, avg prompt tokens saved {:.2}%, cost ${:.4})",
+            totals.shadow_samples,
+            totals.avg_shadow_similarity,
+            totals.avg_shadow_quality_delta,
+            totals.avg_shadow_token_savings * 100.0,
+            totals.shadow_cost_usd
+        );
+    }
+    println!("PII Values Redacted: {}", totals.pii_redactions);
+    for (kind, count) in &totals.pii_by_kind {
+        println!("  {}: {}", kind, count);
+    }
+
+    print_buckets("Daily", snapshot.daily.iter().rev().take(7));
+    print_buckets("Hourly", snapshot.hourly.iter().rev().take(24));
+    Ok(())
+}
+
+/// `verifier test-rules` runs every loaded pack's test cases, or scores a
+/// single `--output` against `--fact`s and lists the rules that fired.
+/// Without `--domain`, every domain pack is loaded.
+fn verifier_command(mut args: impl Iterator<Item = String>) -> Result<(), Box<dyn std::error::Error>> {
+    if args.next().as_deref() != Some("test-rules") {
+        return Err("Usage: optimacore verifier test-rules [--rules <dir>] [--domain <name>]... [--output <text> --fact <text>...]".into());
+    }
+    let mut dir = env::var("OPTIMA_RULES_DIR").unwrap_or_else(|_| "./rules".to_string());
+    let mut domains = Vec::new();
+    let mut output = None;
+    let mut facts = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--rules" => dir = args.next().ok_or("--rules requires a value")?,
+            "--domain" => domains.push(args.next().ok_or("--domain requires a value")?),
+            "--output" => output = Some(args.next().ok_or("--output requires a value")?),
+            "--fact" => facts.push(args.next().ok_or("--fact requires a value")?),
+            other => return Err(format!("Unknown argument '{}'", other).into()),
+        }
+    }
+
+    let rules = RuleSet::load(Path::new(&dir), if domains.is_empty() { None } else { Some(&domains) })?;
+    let names: Vec<&str> = rules.packs().iter().map(|pack| pack.name.as_str()).collect();
+    println!("Rule packs: {}", names.join(", "));
+
+    if let Some(output) = output {
+        for hit in rules.evaluate(&output, &facts) {
+            println!("  {:>5.2}  {}: {}", hit.weight, hit.pack, hit.rule);
+        }
+        let score = rules.score(&output, &facts);
+        println!("Score: {:.2} ({})", score, if score > CONTRADICTION_THRESHOLD { "contradiction" } else { "consistent" });
+        return Ok(());
+    }
+
+    let mut failures = 0;
+    let mut total = 0;
+    for pack in rules.packs() {
+        for test in &pack.tests {
+            total += 1;
+            let score = rules.score(&test.output, &test.facts);
+            let passed = (score > CONTRADICTION_THRESHOLD) == test.contradiction;
+            if !passed {
+                failures += 1;
+            }
+            println!(
+                "{}  {}: {:?} (score {:.2}, expected {})",
+                if passed { "PASS" } else { "FAIL" },
+                pack.name,
+                test.output,
+                score,
+                if test.contradiction { "contradiction" } else { "consistent" }
+            );
+        }
+    }
+    println!("{} of {} rule test(s) passed", total - failures, total);
+    if failures > 0 {
+        return Err(format!("{} rule test(s) failed", failures).into());
+    }
+    Ok(())
+}
+
+fn print_buckets<'a>(title: &str, buckets: impl Iterator<Item = (&'a u64, &'a StatsCounters)>) {
+    println!("\n{} (most recent first):", title);
+    for (start, counters) in buckets {
+        let summary = counters.summary();
+        println!(
+            "  {}  requests={:<6} saved={:>6.2}%  trimmed={}",
+            format_bucket(*start),
+            summary.total_requests,
+            summary.avg_compression_ratio * 100.0,
+            summary.reflections_trimmed
+        );
+    }
+}
+
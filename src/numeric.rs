@@ -0,0 +1,438 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use crate::verifier::split_claims;
+
+static ISO_DATE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\b(\d{4})-(\d{2})-(\d{2})\b").unwrap());
+/// Full month names or their abbreviations, as whole words.
+const MONTH_NAME: &str = r"(january|february|march|april|may|june|july|august|september|october|november|december|jan|feb|mar|apr|jun|jul|aug|sept|sep|oct|nov|dec)\b";
+static MONTH_FIRST_DATE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(&format!(r"(?i)\b{}\.?\s+(\d{{1,2}})(?:st|nd|rd|th)?,?\s+(\d{{4}})\b", MONTH_NAME)).unwrap()
+});
+static DAY_FIRST_DATE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(&format!(r"(?i)\b(\d{{1,2}})(?:st|nd|rd|th)?\s+{}\.?,?\s+(\d{{4}})\b", MONTH_NAME)).unwrap()
+});
+static QUANTITY: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?i)(?P<currency>[$€£])?(?P<number>\d{1,3}(?:,\d{3})+(?:\.\d+)?|\d+(?:\.\d+)?)(?:\s?(?P<scale>thousand|million|billion|trillion|bn)\b)?(?:\s?(?P<unit>%|°[cf]|[a-zµ]+(?:/[a-z]+)?))?",
+    )
+    .unwrap()
+});
+
+const MONTHS: &[&str] = &["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+
+/// What a value measures; values are only compared within one dimension.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Dimension {
+    /// A number of things, e.g. "12 employees", keyed by the singular
+    /// noun; only counts of the same noun are compared.
+    Count(String),
+    Percent,
+    Length,
+    Mass,
+    Time,
+    Data,
+    Speed,
+    Temperature,
+    /// ISO 4217 code.
+    Currency(String),
+    Date,
+}
+
+/// Unit spellings, their dimension, and the factor to the dimension's base
+/// unit (metre, kilogram, second, byte, metre per second).
+const UNITS: &[(&[&str], Dimension, f64)] = &[
+    (&["%", "percent", "pct"], Dimension::Percent, 1.0),
+    (&["mm", "millimeter", "millimeters", "millimetre", "millimetres"], Dimension::Length, 0.001),
+    (&["cm", "centimeter", "centimeters", "centimetre", "centimetres"], Dimension::Length, 0.01),
+    (&["m", "meter", "meters", "metre", "metres"], Dimension::Length, 1.0),
+    (&["km", "kilometer", "kilometers", "kilometre", "kilometres"], Dimension::Length, 1000.0),
+    (&["inch", "inches"], Dimension::Length, 0.0254),
+    (&["ft", "foot", "feet"], Dimension::Length, 0.3048),
+    (&["mi", "mile", "miles"], Dimension::Length, 1609.344),
+    (&["mg", "milligram", "milligrams"], Dimension::Mass, 1e-6),
+    (&["g", "gram", "grams"], Dimension::Mass, 1e-3),
+    (&["kg", "kilogram", "kilograms"], Dimension::Mass, 1.0),
+    (&["t", "tonne", "tonnes", "ton", "tons"], Dimension::Mass, 1000.0),
+    (&["lb", "lbs", "pound", "pounds"], Dimension::Mass, 0.453_592_37),
+    (&["oz", "ounce", "ounces"], Dimension::Mass, 0.028_349_523),
+    (&["ms", "millisecond", "milliseconds"], Dimension::Time, 0.001),
+    (&["s", "sec", "secs", "second", "seconds"], Dimension::Time, 1.0),
+    (&["min", "mins", "minute", "minutes"], Dimension::Time, 60.0),
+    (&["h", "hr", "hrs", "hour", "hours"], Dimension::Time, 3600.0),
+    (&["day", "days"], Dimension::Time, 86_400.0),
+    (&["week", "weeks"], Dimension::Time, 604_800.0),
+    (&["yr", "yrs", "year", "years"], Dimension::Time, 31_557_600.0),
+    (&["byte", "bytes"], Dimension::Data, 1.0),
+    (&["kb", "kilobyte", "kilobytes"], Dimension::Data, 1e3),
+    (&["mb", "megabyte", "megabytes"], Dimension::Data, 1e6),
+    (&["gb", "gigabyte", "gigabytes"], Dimension::Data, 1e9),
+    (&["tb", "terabyte", "terabytes"], Dimension::Data, 1e12),
+    (&["m/s"], Dimension::Speed, 1.0),
+    (&["km/h", "kph"], Dimension::Speed, 1.0 / 3.6),
+    (&["mph"], Dimension::Speed, 0.447_04),
+];
+
+/// A number found in text, normalized to its dimension's base unit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quantity {
+    /// As written, e.g. "330 m" or "March 5, 2021".
+    pub text: String,
+    pub dimension: Dimension,
+    /// Base-unit value; for dates, `yyyymmdd` with unknown parts as zero.
+    pub value: f64,
+    /// For dates, whether month and day are known.
+    pub date_precision: DatePrecision,
+    /// Lowercased words of the clause the value appears in, minus numbers
+    /// and units, which say what the value is about.
+    pub subject: HashSet<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DatePrecision {
+    NotADate,
+    Year,
+    Day,
+}
+
+/// A value in the output that disagrees with a fact about the same subject.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NumericMismatch {
+    pub output_value: String,
+    pub fact_value: String,
+    pub dimension: Dimension,
+    /// `|a - b| / max(|a|, |b|)` after unit normalization; 1 for dates.
+    /// For temperatures, the absolute difference in degrees Celsius, since
+    /// a relative difference in Kelvin hides real disagreements.
+    pub relative_difference: f64,
+}
+
+fn unit(name: &str) -> Option<(Dimension, f64)> {
+    let name = name.to_lowercase();
+    match name.as_str() {
+        "°c" | "celsius" => return Some((Dimension::Temperature, 1.0)),
+        "°f" | "fahrenheit" => return Some((Dimension::Temperature, 1.0)),
+        "kelvin" => return Some((Dimension::Temperature, 1.0)),
+        "usd" | "dollar" | "dollars" => return Some((Dimension::Currency("USD".to_string()), 1.0)),
+        "eur" | "euro" | "euros" => return Some((Dimension::Currency("EUR".to_string()), 1.0)),
+        "gbp" => return Some((Dimension::Currency("GBP".to_string()), 1.0)),
+        _ => {}
+    }
+    UNITS
+        .iter()
+        .find(|(names, _, _)| names.contains(&name.as_str()))
+        .map(|(_, dimension, factor)| (dimension.clone(), *factor))
+}
+
+/// Kelvin for a temperature written in `unit`.
+fn to_kelvin(value: f64, unit: &str) -> f64 {
+    match unit.to_lowercase().as_str() {
+        "°f" | "fahrenheit" => (value - 32.0) * 5.0 / 9.0 + 273.15,
+        "kelvin" => value,
+        _ => value + 273.15,
+    }
+}
+
+fn month_number(name: &str) -> u32 {
+    let prefix = name.to_lowercase();
+    MONTHS.iter().position(|month| prefix.starts_with(month)).map_or(0, |index| index as u32 + 1)
+}
+
+/// Words after a number that say nothing about what it counts.
+const NOT_COUNTED: &[&str] = &[
+    "and", "are", "but", "for", "from", "had", "has", "have", "into", "more", "than", "that", "the", "then", "was", "were", "when",
+    "which", "while", "with",
+];
+
+/// Words that make the number after them a label, as in "chapter 7".
+const LABELS: &[&str] = &[
+    "act", "article", "chapter", "episode", "figure", "level", "no", "number", "page", "part", "phase", "room", "round", "section",
+    "season", "step", "table", "version", "volume",
+];
+
+/// Whether the text before a number ends with a label word.
+fn follows_label(before: &str) -> bool {
+    before
+        .split_whitespace()
+        .next_back()
+        .map(|word| word.trim_end_matches(|c: char| !c.is_alphanumeric()).to_lowercase())
+        .is_some_and(|word| LABELS.contains(&word.as_str()))
+}
+
+/// The singular form of `word` if it can name what a number counts.
+fn counted_noun(word: &str) -> Option<String> {
+    let word = word.to_lowercase();
+    if word.chars().count() < 3 || !word.chars().all(char::is_alphabetic) || NOT_COUNTED.contains(&word.as_str()) {
+        return None;
+    }
+    Some(match word.strip_suffix('s') {
+        Some(singular) if !word.ends_with("ss") && singular.chars().count() >= 3 => singular.to_string(),
+        _ => word,
+    })
+}
+
+fn subject_words(clause: &str) -> HashSet<String> {
+    clause
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() >= 3 && !word.chars().any(|c| c.is_ascii_digit()) && unit(word).is_none())
+        .map(|word| word.to_lowercase())
+        .collect()
+}
+
+/// Every date, percentage, quantity and count in `clause`. Four-digit bare
+/// numbers between 1000 and 2100 are read as years; other numbers without
+/// a unit or a noun after them are skipped, since there is no telling
+/// what they count.
+pub fn extract(clause: &str) -> Vec<Quantity> {
+    let subject = subject_words(clause);
+    let mut quantities = Vec::new();
+    let mut taken: Vec<(usize, usize)> = Vec::new();
+    let mut date = |text: &str, year: u32, month: u32, day: u32, span: (usize, usize), taken: &mut Vec<(usize, usize)>| {
+        taken.push(span);
+        quantities.push(Quantity {
+            text: text.to_string(),
+            dimension: Dimension::Date,
+            value: f64::from(year * 10_000 + month * 100 + day),
+            date_precision: DatePrecision::Day,
+            subject: subject.clone(),
+        });
+    };
+
+    for captures in ISO_DATE.captures_iter(clause) {
+        let whole = captures.get(0).unwrap();
+        let part = |i: usize| captures[i].parse::<u32>().unwrap_or(0);
+        date(whole.as_str(), part(1), part(2), part(3), (whole.start(), whole.end()), &mut taken);
+    }
+    for captures in MONTH_FIRST_DATE.captures_iter(clause) {
+        let whole = captures.get(0).unwrap();
+        let (day, year) = (captures[2].parse().unwrap_or(0), captures[3].parse().unwrap_or(0));
+        date(whole.as_str(), year, month_number(&captures[1]), day, (whole.start(), whole.end()), &mut taken);
+    }
+    for captures in DAY_FIRST_DATE.captures_iter(clause) {
+        let whole = captures.get(0).unwrap();
+        let (day, year) = (captures[1].parse().unwrap_or(0), captures[3].parse().unwrap_or(0));
+        date(whole.as_str(), year, month_number(&captures[2]), day, (whole.start(), whole.end()), &mut taken);
+    }
+
+    for captures in QUANTITY.captures_iter(clause) {
+        let whole = captures.get(0).unwrap();
+        if taken.iter().any(|(start, end)| whole.start() < *end && *start < whole.end()) {
+            continue;
+        }
+        // Skip digits that are part of a word, such as "mp3" or "B2B".
+        if clause[..whole.start()].chars().next_back().is_some_and(|c| c.is_alphanumeric()) {
+            continue;
+        }
+        let Ok(mut value) = captures["number"].replace(',', "").parse::<f64>() else {
+            continue;
+        };
+        value *= match captures.name("scale").map(|scale| scale.as_str().to_lowercase()).as_deref() {
+            Some("thousand") => 1e3,
+            Some("million") => 1e6,
+            Some("billion") | Some("bn") => 1e9,
+            Some("trillion") => 1e12,
+            _ => 1.0,
+        };
+
+        let unit_text = captures.name("unit").map(|unit| unit.as_str());
+        let currency = captures.name("currency").map(|symbol| match symbol.as_str() {
+            "€" => "EUR",
+            "£" => "GBP",
+            _ => "USD",
+        });
+        let number = captures.name("number").unwrap();
+        let number_text = &clause[whole.start()..captures.name("scale").map_or(number.end(), |scale| scale.end())];
+        let (dimension, text, precision) = match (currency, unit_text.and_then(unit)) {
+            (Some(code), _) => (Dimension::Currency(code.to_string()), number_text, DatePrecision::NotADate),
+            (None, Some((Dimension::Temperature, _))) => {
+                value = to_kelvin(value, unit_text.unwrap_or_default());
+                (Dimension::Temperature, whole.as_str(), DatePrecision::NotADate)
+            }
+            (None, Some((dimension, factor))) => {
+                value *= factor;
+                (dimension, whole.as_str(), DatePrecision::NotADate)
+            }
+            (None, None) => {
+                let is_year = captures.name("scale").is_none() && number.as_str().len() == 4 && (1000.0..=2100.0).contains(&value);
+                if is_year {
+                    value *= 10_000.0;
+                    (Dimension::Date, number_text, DatePrecision::Year)
+                } else if let Some(noun) = unit_text.and_then(counted_noun).filter(|_| !follows_label(&clause[..whole.start()])) {
+                    (Dimension::Count(noun), whole.as_str(), DatePrecision::NotADate)
+                } else {
+                    continue;
+                }
+            }
+        };
+        quantities.push(Quantity { text: text.trim().to_string(), dimension, value, date_precision: precision, subject: subject.clone() });
+    }
+    quantities
+}
+
+/// Whether two clauses talk about the same thing: they share at least half
+/// the words of the shorter one.
+fn same_subject(a: &HashSet<String>, b: &HashSet<String>) -> bool {
+    let shared = a.intersection(b).count();
+    shared > 0 && shared * 2 >= a.len().min(b.len())
+}
+
+/// Compares numbers, dates and quantities between output and facts.
+#[derive(Debug, Clone)]
+pub struct NumericChecker {
+    tolerance: f64,
+    temperature_tolerance: f64,
+}
+
+impl NumericChecker {
+    /// `OPTIMA_NUMERIC_TOLERANCE` is the relative difference still counted
+    /// as a match (default 0.02); `OPTIMA_NUMERIC_TEMPERATURE_TOLERANCE`
+    /// the difference in degrees Celsius for temperatures (default 1).
+    /// Dates must match exactly.
+    pub fn from_env() -> Self {
+        let env = |name: &str| std::env::var(name).ok().and_then(|v| v.parse::<f64>().ok());
+        Self {
+            tolerance: env("OPTIMA_NUMERIC_TOLERANCE").unwrap_or(0.02),
+            temperature_tolerance: env("OPTIMA_NUMERIC_TEMPERATURE_TOLERANCE").unwrap_or(1.0),
+        }
+    }
+
+    pub fn tolerance(&self) -> f64 {
+        self.tolerance
+    }
+
+    /// Largest difference from `difference` still counted as a match.
+    fn tolerance_for(&self, dimension: &Dimension) -> f64 {
+        match dimension {
+            Dimension::Temperature => self.temperature_tolerance,
+            _ => self.tolerance,
+        }
+    }
+
+    /// Values in `claim` that no same-subject value of the same dimension in
+    /// `fact` agrees with. A claim value with nothing comparable in the fact
+    /// is not a mismatch.
+    pub fn compare(&self, claim: &str, fact: &str) -> Vec<NumericMismatch> {
+        let fact_quantities: Vec<Quantity> = split_claims(fact).into_iter().flat_map(|(start, end)| extract(&fact[start..end])).collect();
+        let mut mismatches = Vec::new();
+        for quantity in extract(claim) {
+            let candidates: Vec<&Quantity> = fact_quantities
+                .iter()
+                .filter(|fact| fact.dimension == quantity.dimension && same_subject(&fact.subject, &quantity.subject))
+                .collect();
+            let differences: Vec<(f64, &Quantity)> =
+                candidates.iter().map(|fact| (self.difference(&quantity, fact), *fact)).collect();
+            if differences.iter().any(|(difference, _)| *difference <= self.tolerance_for(&quantity.dimension)) {
+                continue;
+            }
+            if let Some((difference, fact)) = differences.into_iter().min_by(|a, b| a.0.total_cmp(&b.0)) {
+                mismatches.push(NumericMismatch {
+                    output_value: quantity.text.clone(),
+                    fact_value: fact.text.clone(),
+                    dimension: quantity.dimension.clone(),
+                    relative_difference: difference,
+                });
+            }
+        }
+        mismatches
+    }
+
+    fn difference(&self, a: &Quantity, b: &Quantity) -> f64 {
+        if a.dimension == Dimension::Date {
+            let (a_value, b_value) = if a.date_precision == DatePrecision::Year || b.date_precision == DatePrecision::Year {
+                ((a.value / 10_000.0).floor(), (b.value / 10_000.0).floor())
+            } else {
+                (a.value, b.value)
+            };
+            return if a_value == b_value { 0.0 } else { 1.0 };
+        }
+        if a.dimension == Dimension::Temperature {
+            return (a.value - b.value).abs();
+        }
+        let scale = a.value.abs().max(b.value.abs());
+        if scale == 0.0 {
+            0.0
+        } else {
+            (a.value - b.value).abs() / scale
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checker() -> NumericChecker {
+        NumericChecker { tolerance: 0.02, temperature_tolerance: 1.0 }
+    }
+
+    fn dimensions(clause: &str) -> Vec<(Dimension, f64)> {
+        extract(clause).into_iter().map(|quantity| (quantity.dimension, quantity.value)).collect()
+    }
+
+    #[test]
+    fn dates_need_a_real_month_name() {
+        assert_eq!(dimensions("It launched on March 5, 2021."), [(Dimension::Date, 20210305.0)]);
+        assert_eq!(dimensions("It launched on 5 Sept. 2021."), [(Dimension::Date, 20210905.0)]);
+        assert_eq!(dimensions("It launched on Mar 5 2021."), [(Dimension::Date, 20210305.0)]);
+        // "market" and "decade" start like months but are not.
+        assert_eq!(dimensions("The market 5, 2021 report"), [(Dimension::Date, 20210000.0)]);
+        assert_eq!(dimensions("The decade 3 2020 plan"), [(Dimension::Date, 20200000.0)]);
+    }
+
+    #[test]
+    fn units_are_normalized() {
+        assert_eq!(dimensions("The tower is 330 m tall."), [(Dimension::Length, 330.0)]);
+        assert_eq!(dimensions("The tower is 1.2 km away."), [(Dimension::Length, 1200.0)]);
+        assert_eq!(dimensions("Revenue was $3 million."), [(Dimension::Currency("USD".to_string()), 3e6)]);
+        assert_eq!(dimensions("Growth hit 12%."), [(Dimension::Percent, 12.0)]);
+    }
+
+    #[test]
+    fn counts_carry_their_noun_and_bare_numbers_are_skipped() {
+        assert_eq!(dimensions("The firm has 12 employees."), [(Dimension::Count("employee".to_string()), 12.0)]);
+        assert_eq!(dimensions("Chapter 7 explains it."), []);
+        assert_eq!(dimensions("See page 12 chapters later."), []);
+        assert_eq!(dimensions("It ranked 3 and then 4."), []);
+    }
+
+    #[test]
+    fn mismatched_values_of_the_same_subject() {
+        let fact = "The Eiffel Tower is 330 m tall.";
+        assert!(checker().compare("The Eiffel Tower is 330 m tall.", fact).is_empty());
+        assert!(checker().compare("The Eiffel Tower is 0.33 km tall.", fact).is_empty());
+        let mismatches = checker().compare("The Eiffel Tower is 300 m tall.", fact);
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].output_value, "300 m");
+        assert_eq!(mismatches[0].fact_value, "330 m");
+    }
+
+    #[test]
+    fn counts_only_compare_with_the_same_noun() {
+        let fact = "The company had 12 employees in its first office.";
+        assert_eq!(checker().compare("The company had 15 employees in its first office.", fact).len(), 1);
+        assert!(checker().compare("The company had 3 offices in its first year, with 12 employees.", fact).is_empty());
+        // Bare numbers about the same subject are not compared.
+        assert!(checker().compare("The company ranked 3 in its first office survey.", "The company ranked 5 in its first office survey.").is_empty());
+    }
+
+    #[test]
+    fn years_compare_at_year_precision() {
+        let fact = "The bridge opened in 1937.";
+        assert!(checker().compare("The bridge opened on May 27, 1937.", fact).is_empty());
+        assert_eq!(checker().compare("The bridge opened in 1938.", fact).len(), 1);
+    }
+
+    #[test]
+    fn temperatures_compare_in_degrees() {
+        let fact = "Water boils at 100 °C at sea level.";
+        let mismatches = checker().compare("Water boils at 95 °C at sea level.", fact);
+        assert_eq!(mismatches.len(), 1);
+        assert!((mismatches[0].relative_difference - 5.0).abs() < 1e-9);
+        assert_eq!(checker().compare("Room temperature is 25 °C indoors.", "Room temperature is 20 °C indoors.").len(), 1);
+        assert!(checker().compare("Water boils at 212 °F at sea level.", fact).is_empty());
+        assert!(checker().compare("Water boils at 100.5 °C at sea level.", fact).is_empty());
+    }
+}
@@ -89,10 +89,6 @@ function check_for_contradiction(output::String, facts::Vector{String})::Float64
                 contradiction_score += 0.5
             end
         end
-
-        if lower_fact == "the capital of france is paris." && occursin("london", lower_output) && occursin("capital", lower_output)
-            contradiction_score += 0.9
-        end
     end
     
     return min(contradiction_score, 1.0)
@@ -0,0 +1,456 @@
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::error::Error;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+use crate::session::now_secs;
+use crate::stats::worker_id;
+
+/// Detectors that run unless a PII file turns them off, most specific first
+/// so that e.g. a card number is not also taken for a phone number.
+const BUILTIN_DETECTORS: &[(&str, &str, Option<Checksum>)] = &[
+    ("API_KEY", r"\b(?:sk-[A-Za-z0-9_-]{20,}|AKIA[0-9A-Z]{16}|AIza[0-9A-Za-z_-]{35}|gh[pousr]_[A-Za-z0-9]{36}|xox[abprs]-[A-Za-z0-9-]{10,})\b", None),
+    ("EMAIL", r"(?i)\b[a-z0-9._%+-]+@[a-z0-9.-]+\.[a-z]{2,}\b", None),
+    ("IBAN", r"\b[A-Z]{2}\d{2}(?: ?[A-Z0-9]{4}){2,7}(?: ?[A-Z0-9]{1,3})?\b", Some(Checksum::Iban)),
+    ("CARD", r"\b\d(?:[ -]?\d){12,18}\b", Some(Checksum::Luhn)),
+    ("PHONE", r"(?:\+|\(|\b)\d[\d ().-]{6,}\d\b", Some(Checksum::Phone)),
+    ("NAME", r"\b(?:Mr|Mrs|Ms|Miss|Dr|Prof)\.? (?P<value>[A-Z][a-z]+(?: [A-Z][a-z]+)?)", None),
+    ("NAME", r"(?i:\bmy name is|\bname:) (?P<value>[A-Z][a-z]+(?: [A-Z][a-z]+)?)", None),
+];
+
+/// Extra validation for a regex match, to drop look-alikes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Checksum {
+    /// Payment card numbers.
+    Luhn,
+    /// ISO 13616 mod-97 check.
+    Iban,
+    /// 10 to 15 digits (8 with a leading `+`) that don't form a date.
+    Phone,
+}
+
+impl Checksum {
+    pub fn validate(self, value: &str) -> bool {
+        match self {
+            Checksum::Luhn => luhn(value),
+            Checksum::Iban => iban(value),
+            Checksum::Phone => phone(value),
+        }
+    }
+}
+
+fn luhn(value: &str) -> bool {
+    let digits: Vec<u32> = value.chars().filter_map(|c| c.to_digit(10)).collect();
+    if !(13..=19).contains(&digits.len()) {
+        return false;
+    }
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &digit)| match (i % 2 == 1, digit * 2) {
+            (true, doubled) if doubled > 9 => doubled - 9,
+            (true, doubled) => doubled,
+            (false, _) => digit,
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+fn iban(value: &str) -> bool {
+    let compact: String = value.chars().filter(|c| !c.is_whitespace()).collect::<String>().to_uppercase();
+    if !(15..=34).contains(&compact.len()) || !compact.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return false;
+    }
+    let (head, tail) = compact.split_at(4);
+    let mut remainder = 0u32;
+    for c in tail.chars().chain(head.chars()) {
+        // Letters count as two digits: A = 10 … Z = 35.
+        let n = c.to_digit(36).unwrap();
+        remainder = if n >= 10 { (remainder * 100 + n) % 97 } else { (remainder * 10 + n) % 97 };
+    }
+    remainder == 1
+}
+
+fn phone(value: &str) -> bool {
+    let digits = value.chars().filter(char::is_ascii_digit).count();
+    let min = if value.starts_with('+') { 8 } else { 10 };
+    let looks_like_date = value.len() == 10 && value.as_bytes()[4] == b'-' && value.as_bytes()[7] == b'-';
+    (min..=15).contains(&digits) && !looks_like_date
+}
+
+/// One kind of PII and how to find it. When the pattern has a group named
+/// `value`, only that group is redacted.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PiiDetector {
+    pub kind: String,
+    pub pattern: String,
+    #[serde(default)]
+    pub checksum: Option<Checksum>,
+    #[serde(skip)]
+    compiled: Option<Regex>,
+}
+
+impl PiiDetector {
+    fn compile(mut self) -> Result<Self, Box<dyn Error>> {
+        self.kind = self.kind.to_uppercase();
+        self.compiled = Some(Regex::new(&self.pattern).map_err(|e| format!("PII detector '{}': {}", self.kind, e))?);
+        Ok(self)
+    }
+
+    fn find(&self, text: &str) -> Vec<(usize, usize)> {
+        let Some(regex) = &self.compiled else {
+            return Vec::new();
+        };
+        regex
+            .captures_iter(text)
+            .filter_map(|captures| captures.name("value").or_else(|| captures.get(0)))
+            .filter(|m| self.checksum.is_none_or(|checksum| checksum.validate(m.as_str())))
+            .map(|m| (m.start(), m.end()))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct PiiFile {
+    /// Keep the built-in detectors alongside the file's own.
+    #[serde(default = "default_builtin")]
+    builtin: bool,
+    /// Built-in kinds to leave alone, e.g. `["PHONE"]`.
+    #[serde(default)]
+    disabled: Vec<String>,
+    /// Known names (customers, staff) to redact wherever they occur.
+    #[serde(default)]
+    names: Vec<String>,
+    #[serde(default)]
+    detectors: Vec<PiiDetector>,
+}
+
+fn default_builtin() -> bool {
+    true
+}
+
+impl Default for PiiFile {
+    fn default() -> Self {
+        Self { builtin: true, disabled: Vec::new(), names: Vec::new(), detectors: Vec::new() }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct PiiEntry {
+    kind: String,
+    placeholder: String,
+    original: String,
+    occurrences: usize,
+}
+
+/// Placeholders handed out for one request and the values behind them. The
+/// same value always gets the same placeholder, and placeholders are
+/// numbered per kind in order of first appearance, so identical prompts
+/// redact identically.
+#[derive(Debug, Clone, Default)]
+pub struct PiiMap {
+    entries: Vec<PiiEntry>,
+}
+
+impl PiiMap {
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn placeholder_for(&mut self, kind: &str, original: &str) -> String {
+        if let Some(entry) = self.entries.iter_mut().find(|entry| entry.kind == kind && entry.original == original) {
+            entry.occurrences += 1;
+            return entry.placeholder.clone();
+        }
+        let n = self.entries.iter().filter(|entry| entry.kind == kind).count() + 1;
+        let placeholder = format!("[{}_{}]", kind, n);
+        self.entries.push(PiiEntry { kind: kind.to_string(), placeholder: placeholder.clone(), original: original.to_string(), occurrences: 1 });
+        placeholder
+    }
+
+    /// Put the original values back in place of their placeholders.
+    pub fn restore(&self, text: &str) -> String {
+        let mut restored = text.to_string();
+        for entry in &self.entries {
+            if restored.contains(&entry.placeholder) {
+                restored = restored.replace(&entry.placeholder, &entry.original);
+            }
+        }
+        restored
+    }
+
+    /// The reverse of `restore`: every known value replaced by its
+    /// placeholder, longest values first.
+    pub fn conceal(&self, text: &str) -> String {
+        let mut entries: Vec<&PiiEntry> = self.entries.iter().collect();
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.original.len()));
+        let mut concealed = text.to_string();
+        for entry in entries {
+            if concealed.contains(&entry.original) {
+                concealed = concealed.replace(&entry.original, &entry.placeholder);
+            }
+        }
+        concealed
+    }
+
+    /// `restore` applied to every string in a JSON value.
+    pub fn restore_value(&self, value: &mut Value) {
+        match value {
+            Value::String(text) => *text = self.restore(text),
+            Value::Array(items) => items.iter_mut().for_each(|item| self.restore_value(item)),
+            Value::Object(fields) => fields.values_mut().for_each(|field| self.restore_value(field)),
+            _ => {}
+        }
+    }
+
+    fn longest_placeholder(&self) -> usize {
+        self.entries.iter().map(|entry| entry.placeholder.len()).max().unwrap_or(0)
+    }
+}
+
+/// Restores placeholders in streamed chunks, holding back a trailing `[…`
+/// until it is known whether it is a placeholder split across chunks.
+#[derive(Debug, Clone, Default)]
+pub struct PiiRestorer {
+    map: PiiMap,
+    pending: String,
+}
+
+impl PiiRestorer {
+    pub fn new(map: PiiMap) -> Self {
+        Self { map, pending: String::new() }
+    }
+
+    pub fn push(&mut self, chunk: &str) -> String {
+        if self.map.is_empty() {
+            return chunk.to_string();
+        }
+        self.pending.push_str(chunk);
+        let hold = match self.pending.rfind('[') {
+            Some(open) if !self.pending[open..].contains(']') && self.pending.len() - open < self.map.longest_placeholder() => open,
+            _ => self.pending.len(),
+        };
+        let ready = self.map.restore(&self.pending[..hold]);
+        self.pending.drain(..hold);
+        ready
+    }
+
+    /// Whatever is still held back, restored.
+    pub fn finish(&mut self) -> String {
+        let rest = self.map.restore(&self.pending);
+        self.pending.clear();
+        rest
+    }
+
+    pub fn restore(&self, text: &str) -> String {
+        self.map.restore(text)
+    }
+}
+
+/// Audit entry for one redacted value. The value itself is never recorded;
+/// `fingerprint` is a keyed hash of it, so repeat occurrences can be
+/// correlated without storing the PII.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PiiRedaction {
+    pub kind: String,
+    pub placeholder: String,
+    pub occurrences: usize,
+    pub fingerprint: String,
+}
+
+#[derive(Serialize)]
+struct AuditRecord<'a> {
+    timestamp: u64,
+    #[serde(flatten)]
+    redaction: &'a PiiRedaction,
+}
+
+/// Replaces PII in prompts with placeholders before they reach HHTC or the
+/// LLM, so the values can be restored in the answer.
+pub struct PiiRedactor {
+    detectors: Vec<PiiDetector>,
+    fingerprint_key: [u8; 32],
+    audit_log: Option<PathBuf>,
+}
+
+impl PiiRedactor {
+    /// Built-in detectors plus those in `OPTIMA_PII_PATH` (default
+    /// `./pii.toml`, optional). `None` when `OPTIMA_PII_REDACTION=off`.
+    ///
+    /// Redactions are appended to `$OPTIMA_DATA_DIR/pii_audit/<worker-id>.jsonl`
+    /// unless `OPTIMA_PII_AUDIT=off`. Fingerprints are keyed with
+    /// `OPTIMA_PII_AUDIT_KEY`; without it they only match within a process.
+    pub fn from_env() -> Result<Option<Self>, Box<dyn Error>> {
+        if matches!(std::env::var("OPTIMA_PII_REDACTION").as_deref(), Ok("off") | Ok("false") | Ok("0")) {
+            return Ok(None);
+        }
+        let path = PathBuf::from(std::env::var("OPTIMA_PII_PATH").unwrap_or_else(|_| "./pii.toml".to_string()));
+        let file = if path.exists() { Self::read_file(&path)? } else { PiiFile::default() };
+        let mut redactor = Self::from_file(file)?;
+        redactor.fingerprint_key = match std::env::var("OPTIMA_PII_AUDIT_KEY") {
+            Ok(key) => blake3::derive_key("optimacore pii audit fingerprint", key.as_bytes()),
+            Err(_) => rand::random(),
+        };
+        redactor.audit_log = match std::env::var("OPTIMA_PII_AUDIT").as_deref() {
+            Ok("off") | Ok("false") | Ok("0") => None,
+            _ => {
+                let data_dir = std::env::var("OPTIMA_DATA_DIR").unwrap_or_else(|_| ".".to_string());
+                Some(Path::new(&data_dir).join("pii_audit").join(format!("{}.jsonl", worker_id())))
+            }
+        };
+        info!("PII redaction enabled with {} detector(s)", redactor.detectors.len());
+        Ok(Some(redactor))
+    }
+
+    fn read_file(path: &Path) -> Result<PiiFile, Box<dyn Error>> {
+        let text = std::fs::read_to_string(path)?;
+        let file = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("yaml") | Some("yml") => serde_yaml::from_str(&text)?,
+            _ => toml::from_str(&text)?,
+        };
+        Ok(file)
+    }
+
+    fn from_file(file: PiiFile) -> Result<Self, Box<dyn Error>> {
+        let mut detectors = file.detectors.into_iter().map(PiiDetector::compile).collect::<Result<Vec<_>, _>>()?;
+        for name in &file.names {
+            let regex = RegexBuilder::new(&format!(r"\b{}\b", regex::escape(name))).case_insensitive(true).build()?;
+            detectors.push(PiiDetector { kind: "NAME".to_string(), pattern: regex.as_str().to_string(), checksum: None, compiled: Some(regex) });
+        }
+        if file.builtin {
+            for &(kind, pattern, checksum) in BUILTIN_DETECTORS {
+                if file.disabled.iter().any(|disabled| disabled.eq_ignore_ascii_case(kind)) {
+                    continue;
+                }
+                let detector = PiiDetector { kind: kind.to_string(), pattern: pattern.to_string(), checksum, compiled: None };
+                detectors.push(detector.compile()?);
+            }
+        }
+        Ok(Self { detectors, fingerprint_key: [0; 32], audit_log: None })
+    }
+
+    /// Replace every detected value in `text` with its placeholder from
+    /// `map`, adding new ones as needed. Detectors run in order and a span
+    /// already claimed by an earlier detector is not matched again.
+    pub fn redact(&self, text: &str, map: &mut PiiMap) -> String {
+        let mut spans: Vec<(usize, usize, &str)> = Vec::new();
+        for detector in &self.detectors {
+            for (start, end) in detector.find(text) {
+                if spans.iter().all(|&(s, e, _)| end <= s || start >= e) {
+                    spans.push((start, end, &detector.kind));
+                }
+            }
+        }
+        if spans.is_empty() {
+            return text.to_string();
+        }
+        spans.sort();
+        let mut redacted = String::with_capacity(text.len());
+        let mut cursor = 0;
+        for (start, end, kind) in spans {
+            redacted.push_str(&text[cursor..start]);
+            redacted.push_str(&map.placeholder_for(kind, &text[start..end]));
+            cursor = end;
+        }
+        redacted.push_str(&text[cursor..]);
+        redacted
+    }
+
+    /// The audit entries for `map`, also appended to the audit log.
+    pub fn audit(&self, map: &PiiMap) -> Vec<PiiRedaction> {
+        let redactions: Vec<PiiRedaction> = map
+            .entries
+            .iter()
+            .map(|entry| PiiRedaction {
+                kind: entry.kind.clone(),
+                placeholder: entry.placeholder.clone(),
+                occurrences: entry.occurrences,
+                fingerprint: blake3::keyed_hash(&self.fingerprint_key, entry.original.as_bytes()).to_hex()[..16].to_string(),
+            })
+            .collect();
+        if let (Some(path), false) = (&self.audit_log, redactions.is_empty()) {
+            if let Err(e) = append_audit(path, &redactions) {
+                warn!("Failed to write PII audit log {:?}: {}", path, e);
+            }
+        }
+        redactions
+    }
+}
+
+fn append_audit(path: &Path, redactions: &[PiiRedaction]) -> Result<(), Box<dyn Error>> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut log = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+    let timestamp = now_secs();
+    let mut lines = Vec::new();
+    for redaction in redactions {
+        serde_json::to_writer(&mut lines, &AuditRecord { timestamp, redaction })?;
+        lines.push(b'\n');
+    }
+    log.write_all(&lines)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redactor() -> PiiRedactor {
+        PiiRedactor::from_file(PiiFile::default()).unwrap()
+    }
+
+    #[test]
+    fn luhn_accepts_valid_card_numbers_only() {
+        assert!(luhn("4111 1111 1111 1111"));
+        assert!(luhn("5500-0000-0000-0004"));
+        assert!(!luhn("4111 1111 1111 1112"));
+        // Too short to be a card, though the checksum works out.
+        assert!(!luhn("4242424242"));
+    }
+
+    #[test]
+    fn iban_checks_mod_97() {
+        assert!(iban("GB82 WEST 1234 5698 7654 32"));
+        assert!(iban("DE89370400440532013000"));
+        assert!(!iban("GB82 WEST 1234 5698 7654 33"));
+        assert!(!iban("GB82"));
+    }
+
+    #[test]
+    fn phone_needs_enough_digits_and_no_date() {
+        assert!(phone("+44 20 7946 0958"));
+        assert!(phone("(555) 123-4567"));
+        assert!(!phone("555-1234"));
+        assert!(!phone("2024-01-15"));
+    }
+
+    #[test]
+    fn redact_restore_and_conceal_round_trip() {
+        let mut map = PiiMap::default();
+        let text = "Mail jane@example.com or call +44 20 7946 0958; jane@example.com again.";
+        let redacted = redactor().redact(text, &mut map);
+        assert_eq!(redacted, "Mail [EMAIL_1] or call [PHONE_1]; [EMAIL_1] again.");
+        assert_eq!(map.restore(&redacted), text);
+        assert_eq!(map.conceal(text), redacted);
+    }
+
+    #[test]
+    fn restorer_handles_a_placeholder_split_across_chunks() {
+        let mut map = PiiMap::default();
+        redactor().redact("Contact jane@example.com", &mut map);
+        let mut restorer = PiiRestorer::new(map);
+
+        let mut streamed = restorer.push("Write to [EMA");
+        assert_eq!(streamed, "Write to ");
+        streamed.push_str(&restorer.push("IL_"));
+        streamed.push_str(&restorer.push("1] today [not a placeholder"));
+        streamed.push_str(&restorer.finish());
+        assert_eq!(streamed, "Write to jane@example.com today [not a placeholder");
+    }
+}
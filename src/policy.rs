@@ -0,0 +1,304 @@
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+const REDACTED: &str = "[REDACTED]";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyAction {
+    /// Reject the request (input) or withhold the answer (output).
+    Block,
+    /// Replace the matched text with `[REDACTED]`.
+    Redact,
+    /// Let the text through, but record the match.
+    Flag,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyStage {
+    Input,
+    Output,
+}
+
+fn default_threshold() -> f64 {
+    1.0
+}
+
+/// One policy rule. It matches when any `terms` entry occurs as a whole
+/// word, any `patterns` regex matches, or the summed `lexicon` weights of
+/// the words present reach `threshold`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PolicyRule {
+    pub name: String,
+    /// Reported category; the rule name when unset.
+    #[serde(default)]
+    pub category: Option<String>,
+    pub action: PolicyAction,
+    /// Stages the rule runs at; both when empty.
+    #[serde(default)]
+    pub applies_to: Vec<PolicyStage>,
+    #[serde(default)]
+    pub terms: Vec<String>,
+    #[serde(default)]
+    pub patterns: Vec<String>,
+    #[serde(default)]
+    pub lexicon: BTreeMap<String, f64>,
+    #[serde(default = "default_threshold")]
+    pub threshold: f64,
+    #[serde(skip)]
+    compiled: Vec<Regex>,
+    #[serde(skip)]
+    lexicon_compiled: Vec<(Regex, f64)>,
+}
+
+/// What one rule did to one piece of text.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyDecision {
+    pub stage: PolicyStage,
+    pub rule: String,
+    pub category: String,
+    pub action: PolicyAction,
+    pub matches: usize,
+    /// Lexicon score, for rules that use one.
+    pub score: Option<f64>,
+}
+
+/// The text after policy, and what was done to it.
+#[derive(Debug, Clone)]
+pub struct PolicyOutcome {
+    pub text: String,
+    pub decisions: Vec<PolicyDecision>,
+}
+
+impl PolicyOutcome {
+    pub fn blocked(&self) -> bool {
+        self.decisions.iter().any(|decision| decision.action == PolicyAction::Block)
+    }
+}
+
+/// A request or answer a `Block` rule stopped.
+#[derive(Debug, Clone)]
+pub struct PolicyViolation {
+    pub decisions: Vec<PolicyDecision>,
+}
+
+impl fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let blocking: Vec<String> = self
+            .decisions
+            .iter()
+            .filter(|decision| decision.action == PolicyAction::Block)
+            .map(|decision| format!("{:?} {} ({})", decision.stage, decision.rule, decision.category).to_lowercase())
+            .collect();
+        write!(f, "blocked by content policy: {}", blocking.join(", "))
+    }
+}
+
+impl Error for PolicyViolation {}
+
+fn word_regex(word: &str) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(&format!(r"\b{}\b", regex::escape(word))).case_insensitive(true).build()
+}
+
+impl PolicyRule {
+    fn compile(mut self) -> Result<Self, Box<dyn Error>> {
+        let invalid = |e: regex::Error| format!("policy rule '{}': {}", self.name, e);
+        let mut compiled = Vec::new();
+        for term in &self.terms {
+            compiled.push(word_regex(term).map_err(invalid)?);
+        }
+        for pattern in &self.patterns {
+            compiled.push(Regex::new(pattern).map_err(invalid)?);
+        }
+        let mut lexicon = Vec::new();
+        for (word, weight) in &self.lexicon {
+            lexicon.push((word_regex(word).map_err(invalid)?, *weight));
+        }
+        self.compiled = compiled;
+        self.lexicon_compiled = lexicon;
+        Ok(self)
+    }
+
+    fn applies_to(&self, stage: PolicyStage) -> bool {
+        self.applies_to.is_empty() || self.applies_to.contains(&stage)
+    }
+
+    /// Where the rule matched, or `None` when it does not fire.
+    fn matches(&self, text: &str) -> Option<RuleMatch> {
+        let mut spans: Vec<(usize, usize)> =
+            self.compiled.iter().flat_map(|regex| regex.find_iter(text).map(|m| (m.start(), m.end()))).collect();
+        let mut score = None;
+        if !self.lexicon_compiled.is_empty() {
+            let mut total = 0.0;
+            let mut lexicon_spans = Vec::new();
+            for (regex, weight) in &self.lexicon_compiled {
+                for m in regex.find_iter(text) {
+                    total += weight;
+                    lexicon_spans.push((m.start(), m.end()));
+                }
+            }
+            score = Some(total);
+            if total >= self.threshold {
+                spans.extend(lexicon_spans);
+            }
+        }
+        if spans.is_empty() {
+            None
+        } else {
+            spans.sort();
+            Some(RuleMatch { spans, score })
+        }
+    }
+}
+
+/// Byte ranges one rule matched, and its lexicon score if it has a lexicon.
+struct RuleMatch {
+    spans: Vec<(usize, usize)>,
+    score: Option<f64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct PolicyFile {
+    #[serde(default)]
+    rules: Vec<PolicyRule>,
+}
+
+/// Blocklists, regexes and scored lexicons applied to prompts and answers.
+#[derive(Debug, Clone)]
+pub struct ContentPolicy {
+    rules: Vec<PolicyRule>,
+}
+
+impl ContentPolicy {
+    /// Load `OPTIMA_POLICY_PATH` (default `./policy.toml`; `.yaml`/`.yml`
+    /// also accepted). `None` when the file does not exist.
+    pub fn from_env() -> Result<Option<Self>, Box<dyn Error>> {
+        let path = PathBuf::from(std::env::var("OPTIMA_POLICY_PATH").unwrap_or_else(|_| "./policy.toml".to_string()));
+        if !path.exists() {
+            return Ok(None);
+        }
+        let policy = Self::load(&path)?;
+        info!("Loaded {} content policy rule(s) from {:?}", policy.rules.len(), path);
+        Ok(Some(policy))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let text = std::fs::read_to_string(path)?;
+        let file: PolicyFile = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("yaml") | Some("yml") => serde_yaml::from_str(&text)?,
+            _ => toml::from_str(&text)?,
+        };
+        let rules = file.rules.into_iter().map(PolicyRule::compile).collect::<Result<_, _>>()?;
+        Ok(Self { rules })
+    }
+
+    /// Whether any rule blocks or redacts answers. Such rules cannot be
+    /// applied to a streamed answer, whose chunks are sent as they arrive.
+    pub fn rewrites_output(&self) -> bool {
+        self.rules.iter().any(|rule| rule.applies_to(PolicyStage::Output) && rule.action != PolicyAction::Flag)
+    }
+
+    /// Run every rule for `stage` over `text`. Redactions are applied to
+    /// the returned text; blocks and flags are only recorded.
+    pub fn apply(&self, text: &str, stage: PolicyStage) -> PolicyOutcome {
+        let mut decisions = Vec::new();
+        let mut redact: Vec<(usize, usize)> = Vec::new();
+        for rule in self.rules.iter().filter(|rule| rule.applies_to(stage)) {
+            let Some(RuleMatch { spans, score }) = rule.matches(text) else {
+                continue;
+            };
+            if rule.action == PolicyAction::Redact {
+                redact.extend(&spans);
+            }
+            decisions.push(PolicyDecision {
+                stage,
+                rule: rule.name.clone(),
+                category: rule.category.clone().unwrap_or_else(|| rule.name.clone()),
+                action: rule.action,
+                matches: spans.len(),
+                score,
+            });
+        }
+        PolicyOutcome { text: redact_spans(text, redact), decisions }
+    }
+}
+
+fn redact_spans(text: &str, mut spans: Vec<(usize, usize)>) -> String {
+    if spans.is_empty() {
+        return text.to_string();
+    }
+    spans.sort();
+    let mut redacted = String::with_capacity(text.len());
+    let mut cursor = 0;
+    for (start, end) in spans {
+        if start < cursor {
+            // Overlaps the previous redaction; widen it.
+            cursor = cursor.max(end);
+            continue;
+        }
+        redacted.push_str(&text[cursor..start]);
+        redacted.push_str(REDACTED);
+        cursor = end;
+    }
+    redacted.push_str(&text[cursor..]);
+    redacted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(toml: &str) -> ContentPolicy {
+        let file: PolicyFile = toml::from_str(toml).unwrap();
+        ContentPolicy { rules: file.rules.into_iter().map(|rule| rule.compile().unwrap()).collect() }
+    }
+
+    #[test]
+    fn lexicon_scores_are_not_capped() {
+        let policy = policy(
+            r#"
+            [[rules]]
+            name = "hostile"
+            action = "flag"
+            lexicon = { hate = 0.8, destroy = 0.8 }
+            threshold = 1.5
+            "#,
+        );
+        let outcome = policy.apply("I hate this and will destroy it.", PolicyStage::Output);
+        assert_eq!(outcome.decisions.len(), 1);
+        assert_eq!(outcome.decisions[0].score, Some(1.6));
+        assert!(policy.apply("I hate this.", PolicyStage::Output).decisions.is_empty());
+    }
+
+    #[test]
+    fn redaction_and_output_rules() {
+        let redacting = policy(
+            r#"
+            [[rules]]
+            name = "codename"
+            action = "redact"
+            applies_to = ["output"]
+            terms = ["bluebird"]
+            "#,
+        );
+        assert!(redacting.rewrites_output());
+        assert_eq!(redacting.apply("Project Bluebird ships.", PolicyStage::Output).text, "Project [REDACTED] ships.");
+        assert_eq!(redacting.apply("Project Bluebird ships.", PolicyStage::Input).text, "Project Bluebird ships.");
+
+        let flags_only = policy(
+            r#"
+            [[rules]]
+            name = "codename"
+            action = "flag"
+            terms = ["bluebird"]
+            "#,
+        );
+        assert!(!flags_only.rewrites_output());
+    }
+}
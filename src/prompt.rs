@@ -0,0 +1,134 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+    Tool,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::System => "system",
+            Role::User => "user",
+            Role::Assistant => "assistant",
+            Role::Tool => "tool",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: Role,
+    pub content: String,
+    /// Id of the tool call this message answers; only set for `Role::Tool`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+}
+
+impl ChatMessage {
+    pub fn new(role: Role, content: impl Into<String>) -> Self {
+        Self { role, content: content.into(), tool_call_id: None }
+    }
+
+    pub fn system(content: impl Into<String>) -> Self {
+        Self::new(Role::System, content)
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        Self::new(Role::User, content)
+    }
+
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self::new(Role::Assistant, content)
+    }
+
+    pub fn tool(tool_call_id: impl Into<String>, content: impl Into<String>) -> Self {
+        Self { role: Role::Tool, content: content.into(), tool_call_id: Some(tool_call_id.into()) }
+    }
+}
+
+/// Where retrieved EKF knowledge goes in the message list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KnowledgePlacement {
+    /// A dedicated system message.
+    System,
+    /// A user-role context message placed just before the prompt, for
+    /// models that weigh system messages poorly.
+    Context,
+}
+
+/// Assembles the messages sent to the LLM from the (compressed) prompt and
+/// the retrieved EKF knowledge.
+#[derive(Debug, Clone)]
+pub struct PromptBuilder {
+    system_prompt: Option<String>,
+    knowledge_template: String,
+    knowledge_placement: KnowledgePlacement,
+}
+
+impl PromptBuilder {
+    pub const DEFAULT_KNOWLEDGE_TEMPLATE: &'static str = "Context from Knowledge Folder: {knowledge}";
+
+    /// Configured through `OPTIMA_SYSTEM_PROMPT`, `OPTIMA_KNOWLEDGE_TEMPLATE`
+    /// (with a `{knowledge}` placeholder) and `OPTIMA_KNOWLEDGE_ROLE`
+    /// (`system` or `context`).
+    pub fn new() -> Self {
+        let knowledge_placement = match std::env::var("OPTIMA_KNOWLEDGE_ROLE").as_deref() {
+            Ok("context") => KnowledgePlacement::Context,
+            _ => KnowledgePlacement::System,
+        };
+        Self {
+            system_prompt: std::env::var("OPTIMA_SYSTEM_PROMPT").ok(),
+            knowledge_template: std::env::var("OPTIMA_KNOWLEDGE_TEMPLATE")
+                .unwrap_or_else(|_| Self::DEFAULT_KNOWLEDGE_TEMPLATE.to_string()),
+            knowledge_placement,
+        }
+    }
+
+    pub fn with_template(mut self, template: impl Into<String>, placement: KnowledgePlacement) -> Self {
+        self.knowledge_template = template.into();
+        self.knowledge_placement = placement;
+        self
+    }
+
+    pub fn build(&self, prompt: &str, knowledge: &[String]) -> Vec<ChatMessage> {
+        let mut messages = Vec::new();
+        if let Some(system_prompt) = &self.system_prompt {
+            messages.push(ChatMessage::system(system_prompt.clone()));
+        }
+        if !knowledge.is_empty() {
+            let content = self.knowledge_template.replace("{knowledge}", &knowledge.join("\n"));
+            messages.push(match self.knowledge_placement {
+                KnowledgePlacement::System => ChatMessage::system(content),
+                KnowledgePlacement::Context => ChatMessage::user(content),
+            });
+        }
+        messages.push(ChatMessage::user(prompt));
+        messages
+    }
+}
+
+impl Default for PromptBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Render messages as a single completion-style prompt for backends without
+/// a chat format. System and user turns are emitted verbatim so that a
+/// knowledge message followed by a prompt reads exactly as it always has.
+pub fn flatten_messages(messages: &[ChatMessage]) -> String {
+    messages
+        .iter()
+        .map(|message| match message.role {
+            Role::System | Role::User => message.content.clone(),
+            Role::Assistant => format!("Assistant: {}", message.content),
+            Role::Tool => format!("Tool result: {}", message.content),
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
@@ -0,0 +1,160 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use tokio::sync::Mutex;
+use tracing::info;
+
+use crate::generation::{GenerationParam, GenerationParams};
+use crate::llm_backend::{Completion, LlmBackend};
+use crate::prompt::ChatMessage;
+use crate::usage::TokenUsage;
+
+/// Every optional parameter; replay accepts whatever was recorded.
+const ALL_PARAMS: &[GenerationParam] = &[
+    GenerationParam::Stop,
+    GenerationParam::Seed,
+    GenerationParam::PresencePenalty,
+    GenerationParam::FrequencyPenalty,
+    GenerationParam::LogitBias,
+];
+
+/// One recorded request/response pair.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FixtureEntry {
+    pub messages: Vec<ChatMessage>,
+    pub params: GenerationParams,
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<TokenUsage>,
+}
+
+/// Recorded responses keyed by `prompt_hash`. Stored as pretty JSON so
+/// fixtures diff well in review.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Fixture {
+    pub entries: BTreeMap<String, FixtureEntry>,
+}
+
+impl Fixture {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+    }
+
+    pub async fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let tmp_path = path.with_extension("json.tmp");
+        tokio::fs::write(&tmp_path, serde_json::to_vec_pretty(self)?).await?;
+        tokio::fs::rename(&tmp_path, path).await?;
+        Ok(())
+    }
+}
+
+/// BLAKE3 of the assembled messages and generation params.
+pub fn prompt_hash(messages: &[ChatMessage], params: &GenerationParams) -> String {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(serde_json::to_string(messages).unwrap_or_default().as_bytes());
+    hasher.update(&[0]);
+    hasher.update(serde_json::to_string(params).unwrap_or_default().as_bytes());
+    hasher.finalize().to_hex().to_string()
+}
+
+/// Wraps a live backend and writes every response it returns to a fixture
+/// file. Streaming requests are recorded as one non-streamed generation.
+/// Saves are serialized by the fixture lock so concurrent requests cannot
+/// interleave their writes.
+pub struct RecordingBackend {
+    inner: Box<dyn LlmBackend>,
+    path: PathBuf,
+    fixture: Mutex<Fixture>,
+}
+
+impl RecordingBackend {
+    /// Appends to the fixture at `path` if it already exists.
+    pub fn new(inner: Box<dyn LlmBackend>, path: PathBuf) -> Result<Self, Box<dyn Error>> {
+        let fixture = Fixture::load(&path)?;
+        info!("Recording {} responses to {:?}", inner.name(), path);
+        Ok(Self { inner, path, fixture: Mutex::new(fixture) })
+    }
+}
+
+#[async_trait]
+impl LlmBackend for RecordingBackend {
+    fn name(&self) -> &'static str {
+        "record"
+    }
+
+    fn default_endpoint(&self) -> &'static str {
+        self.inner.default_endpoint()
+    }
+
+    fn model(&self) -> Option<&str> {
+        self.inner.model()
+    }
+
+    fn supported_params(&self) -> &'static [GenerationParam] {
+        self.inner.supported_params()
+    }
+
+    async fn generate(&self, endpoint: &str, messages: &[ChatMessage], params: &GenerationParams) -> Result<Completion, Box<dyn Error>> {
+        let completion = self.inner.generate(endpoint, messages, params).await?;
+        let entry = FixtureEntry {
+            messages: messages.to_vec(),
+            params: params.clone(),
+            text: completion.text.clone(),
+            usage: completion.usage,
+        };
+        let mut fixture = self.fixture.lock().await;
+        fixture.entries.insert(prompt_hash(messages, params), entry);
+        fixture.save(&self.path).await?;
+        Ok(completion)
+    }
+}
+
+/// Serves responses from a fixture file without any network access. A
+/// prompt that was never recorded is an error, so tests fail loudly when
+/// the pipeline's output to the LLM changes.
+pub struct ReplayBackend {
+    path: PathBuf,
+    fixture: Fixture,
+}
+
+impl ReplayBackend {
+    pub fn new(path: PathBuf) -> Result<Self, Box<dyn Error>> {
+        if !path.exists() {
+            return Err(format!("LLM fixture file {:?} not found; record one with LLM_BACKEND=record", path).into());
+        }
+        let fixture = Fixture::load(&path)?;
+        info!("Replaying {} recorded LLM responses from {:?}", fixture.entries.len(), path);
+        Ok(Self { path, fixture })
+    }
+}
+
+#[async_trait]
+impl LlmBackend for ReplayBackend {
+    fn name(&self) -> &'static str {
+        "replay"
+    }
+
+    fn default_endpoint(&self) -> &'static str {
+        "replay://fixture"
+    }
+
+    fn supported_params(&self) -> &'static [GenerationParam] {
+        ALL_PARAMS
+    }
+
+    async fn generate(&self, _endpoint: &str, messages: &[ChatMessage], params: &GenerationParams) -> Result<Completion, Box<dyn Error>> {
+        let hash = prompt_hash(messages, params);
+        match self.fixture.entries.get(&hash) {
+            Some(entry) => Ok(Completion { text: entry.text.clone(), usage: entry.usage }),
+            None => Err(format!("No recorded LLM response for prompt hash {} in {:?}", hash, self.path).into()),
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+use crate::generation::GenerationParams;
+use crate::structured::OutputSchema;
+
+tokio::task_local! {
+    /// Deadline of the request whose stage is currently running, for code
+    /// below the pipeline (such as the HTTP transport) that is not handed
+    /// `RequestOptions`.
+    static STAGE_DEADLINE: Option<Instant>;
+}
+
+/// Time left before the deadline of the enclosing `run_stage`, if any.
+pub fn stage_remaining() -> Option<Duration> {
+    STAGE_DEADLINE
+        .try_with(|deadline| *deadline)
+        .ok()
+        .flatten()
+        .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+}
+
+/// Per-request controls threaded through every pipeline stage.
+#[derive(Debug, Clone, Default)]
+pub struct RequestOptions {
+    /// Absolute point in time by which the response must be produced.
+    pub deadline: Option<Instant>,
+    /// Cancelling this token interrupts whichever stage is running.
+    pub cancel: CancellationToken,
+    /// Sampling settings for this request; the client's configured defaults
+    /// when `None`.
+    pub params: Option<GenerationParams>,
+    /// When set, the output must be JSON matching this schema. Not applied
+    /// to streamed responses.
+    pub schema: Option<Arc<OutputSchema>>,
+}
+
+/// Why a stage did not run to completion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interrupted {
+    Cancelled,
+    DeadlineExceeded,
+}
+
+impl fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Interrupted::Cancelled => write!(f, "request cancelled"),
+            Interrupted::DeadlineExceeded => write!(f, "request deadline exceeded"),
+        }
+    }
+}
+
+impl Error for Interrupted {}
+
+impl RequestOptions {
+    pub fn with_timeout(timeout: Duration) -> Self {
+        Self { deadline: Some(Instant::now() + timeout), ..Self::default() }
+    }
+
+    pub fn with_params(mut self, params: GenerationParams) -> Self {
+        self.params = Some(params);
+        self
+    }
+
+    pub fn with_schema(mut self, schema: OutputSchema) -> Self {
+        self.schema = Some(Arc::new(schema));
+        self
+    }
+
+    /// Time left before the deadline, or `None` when there is no deadline.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// Whether at least `needed` remains before the deadline.
+    pub fn has_time_for(&self, needed: Duration) -> bool {
+        self.remaining().is_none_or(|remaining| remaining >= needed)
+    }
+
+    pub fn check(&self) -> Result<(), Interrupted> {
+        if self.cancel.is_cancelled() {
+            Err(Interrupted::Cancelled)
+        } else if self.remaining() == Some(Duration::ZERO) {
+            Err(Interrupted::DeadlineExceeded)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Drive `stage` to completion unless the request is cancelled or the
+    /// deadline passes first, in which case the stage future is dropped.
+    pub async fn run_stage<F: Future>(&self, stage: F) -> Result<F::Output, Interrupted> {
+        self.check()?;
+        let deadline = async {
+            match self.deadline {
+                Some(deadline) => tokio::time::sleep_until(deadline).await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            output = STAGE_DEADLINE.scope(self.deadline, stage) => Ok(output),
+            _ = self.cancel.cancelled() => Err(Interrupted::Cancelled),
+            _ = deadline => Err(Interrupted::DeadlineExceeded),
+        }
+    }
+}
@@ -0,0 +1,432 @@
+use rand::Rng;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::{Client, Response, StatusCode};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{info, warn};
+
+use crate::request::stage_remaining;
+
+/// Upper bound on how long a server-supplied `Retry-After` may stall a request.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+fn env_millis(name: &str, default: u64) -> Duration {
+    Duration::from_millis(std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default))
+}
+
+fn env_u32(name: &str, default: u32) -> u32 {
+    std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+/// Errors raised by the transport itself rather than by reqwest.
+#[derive(Debug, Clone)]
+pub enum TransportError {
+    /// The endpoint's circuit breaker is open; the request was not sent.
+    CircuitOpen { endpoint: String },
+    /// The endpoint answered with a non-success status.
+    Status { endpoint: String, status: u16, body: String },
+}
+
+impl fmt::Display for TransportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransportError::CircuitOpen { endpoint } => write!(f, "circuit breaker open for {}", endpoint),
+            TransportError::Status { endpoint, status, body } => {
+                write!(f, "LLM API request to {} failed: {} - {}", endpoint, status, body)
+            }
+        }
+    }
+}
+
+impl Error for TransportError {}
+
+/// Whether `error` means the endpoint is down or overloaded rather than
+/// that the request itself was bad: a refused connection, a timeout, a 5xx
+/// or an open circuit. Only these are worth trying on another endpoint.
+pub fn is_endpoint_failure(error: &(dyn Error + 'static)) -> bool {
+    if let Some(error) = error.downcast_ref::<TransportError>() {
+        return match error {
+            TransportError::CircuitOpen { .. } => true,
+            TransportError::Status { status, .. } => *status >= 500,
+        };
+    }
+    error.downcast_ref::<reqwest::Error>().is_some_and(|e| e.is_connect() || e.is_timeout())
+}
+
+/// Exponential backoff with full jitter.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn from_env() -> Self {
+        Self {
+            max_retries: env_u32("LLM_MAX_RETRIES", 3),
+            base_delay: env_millis("LLM_RETRY_BASE_MS", 250),
+            max_delay: env_millis("LLM_RETRY_MAX_MS", 10_000),
+        }
+    }
+
+    /// Delay before retry number `attempt` (0-based).
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let ceiling = self.base_delay.saturating_mul(2u32.saturating_pow(attempt)).min(self.max_delay);
+        let jitter = rand::thread_rng().gen_range(0.0..=1.0);
+        ceiling.mul_f64(jitter)
+    }
+}
+
+/// Parse a `Retry-After` header given either as delta-seconds or an HTTP date.
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    let delay = match value.parse::<u64>() {
+        Ok(secs) => Duration::from_secs(secs),
+        Err(_) => httpdate::parse_http_date(value)
+            .ok()?
+            .duration_since(SystemTime::now())
+            .unwrap_or(Duration::ZERO),
+    };
+    Some(delay.min(MAX_RETRY_AFTER))
+}
+
+#[derive(Debug, Clone, Copy)]
+enum BreakerState {
+    Closed { failures: u32 },
+    Open { until: Instant },
+    /// Cooldown elapsed; a single trial request is in flight.
+    HalfOpen { since: Instant },
+}
+
+/// Whether a retry after `delay` would still start before the deadline of
+/// the current request stage.
+fn fits_deadline(delay: Duration) -> bool {
+    stage_remaining().is_none_or(|remaining| delay < remaining)
+}
+
+/// Per-endpoint circuit breaker. After `failure_threshold` consecutive
+/// failures the circuit opens and requests fail fast for `cooldown`; then a
+/// single trial request decides whether it closes again. A trial that has
+/// not reported back within another `cooldown` (say its future was dropped)
+/// is written off and the next request becomes the trial.
+#[derive(Debug, Clone)]
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    cooldown: Duration,
+    state: BreakerState,
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32, cooldown: Duration) -> Self {
+        Self { failure_threshold: failure_threshold.max(1), cooldown, state: BreakerState::Closed { failures: 0 } }
+    }
+
+    /// Whether a request may be sent now.
+    pub fn allow(&mut self) -> bool {
+        match self.state {
+            BreakerState::Closed { .. } => true,
+            BreakerState::Open { until } if Instant::now() >= until => {
+                self.state = BreakerState::HalfOpen { since: Instant::now() };
+                true
+            }
+            BreakerState::HalfOpen { since } if since.elapsed() >= self.cooldown => {
+                warn!("Circuit breaker trial request never completed; allowing another.");
+                self.state = BreakerState::HalfOpen { since: Instant::now() };
+                true
+            }
+            BreakerState::Open { .. } | BreakerState::HalfOpen { .. } => false,
+        }
+    }
+
+    pub fn record_success(&mut self) {
+        self.state = BreakerState::Closed { failures: 0 };
+    }
+
+    pub fn record_failure(&mut self) {
+        self.state = match self.state {
+            BreakerState::Closed { failures } if failures + 1 < self.failure_threshold => {
+                BreakerState::Closed { failures: failures + 1 }
+            }
+            _ => BreakerState::Open { until: Instant::now() + self.cooldown },
+        };
+    }
+
+    pub fn is_open(&self) -> bool {
+        !matches!(self.state, BreakerState::Closed { .. })
+    }
+}
+
+/// Shared HTTP layer for all LLM backends: timeouts, retries on 429/5xx and
+/// connection errors, and a circuit breaker per endpoint.
+pub struct HttpTransport {
+    client: Client,
+    retry: RetryPolicy,
+    read_timeout: Duration,
+    breaker_threshold: u32,
+    breaker_cooldown: Duration,
+    breakers: Mutex<HashMap<String, CircuitBreaker>>,
+}
+
+impl HttpTransport {
+    /// Configured through `LLM_CONNECT_TIMEOUT_MS`, `LLM_READ_TIMEOUT_MS`,
+    /// `LLM_MAX_RETRIES`, `LLM_RETRY_BASE_MS`, `LLM_RETRY_MAX_MS`,
+    /// `LLM_BREAKER_THRESHOLD` and `LLM_BREAKER_COOLDOWN_MS`.
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        let client = Client::builder()
+            .connect_timeout(env_millis("LLM_CONNECT_TIMEOUT_MS", 5_000))
+            .build()?;
+        Ok(Self::new(
+            client,
+            RetryPolicy::from_env(),
+            env_millis("LLM_READ_TIMEOUT_MS", 120_000),
+            env_u32("LLM_BREAKER_THRESHOLD", 5),
+            env_millis("LLM_BREAKER_COOLDOWN_MS", 30_000),
+        ))
+    }
+
+    pub fn new(client: Client, retry: RetryPolicy, read_timeout: Duration, breaker_threshold: u32, breaker_cooldown: Duration) -> Self {
+        Self {
+            client,
+            retry,
+            read_timeout,
+            breaker_threshold,
+            breaker_cooldown,
+            breakers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Maximum time to wait for a full response, or between two chunks of a
+    /// streamed one.
+    pub fn read_timeout(&self) -> Duration {
+        self.read_timeout
+    }
+
+    pub fn is_circuit_open(&self, endpoint: &str) -> bool {
+        self.breakers.lock().unwrap().get(endpoint).is_some_and(CircuitBreaker::is_open)
+    }
+
+    /// One-shot GET used for health checks: no retries, no circuit breaker.
+    pub async fn probe(&self, url: &str) -> bool {
+        let timeout = env_millis("LLM_HEALTH_TIMEOUT_MS", 2_000);
+        match self.client.get(url).timeout(timeout).send().await {
+            Ok(response) => response.status().is_success(),
+            Err(_) => false,
+        }
+    }
+
+    fn with_breaker<T>(&self, endpoint: &str, f: impl FnOnce(&mut CircuitBreaker) -> T) -> T {
+        let mut breakers = self.breakers.lock().unwrap();
+        let breaker = breakers
+            .entry(endpoint.to_string())
+            .or_insert_with(|| CircuitBreaker::new(self.breaker_threshold, self.breaker_cooldown));
+        f(breaker)
+    }
+
+    /// POST `payload` as JSON, retrying transient failures. `stream` disables
+    /// the whole-response timeout; streamed bodies are bounded per chunk.
+    /// Inside `RequestOptions::run_stage`, a retry that could not start
+    /// before the request deadline is not attempted: the last error is
+    /// returned instead of sleeping into the deadline.
+    pub async fn post_json(&self, endpoint: &str, payload: &Value, bearer: Option<&str>, stream: bool) -> Result<Response, Box<dyn Error>> {
+        let mut attempt = 0;
+        loop {
+            if !self.with_breaker(endpoint, CircuitBreaker::allow) {
+                warn!("Circuit open for {}; failing fast.", endpoint);
+                return Err(Box::new(TransportError::CircuitOpen { endpoint: endpoint.to_string() }));
+            }
+
+            info!("Sending request to LLM API: {} (attempt {})", endpoint, attempt + 1);
+            let mut request = self.client.post(endpoint).json(payload);
+            if !stream {
+                request = request.timeout(self.read_timeout);
+            }
+            if let Some(token) = bearer {
+                request = request.bearer_auth(token);
+            }
+
+            let delay = match request.send().await {
+                Ok(response) if response.status().is_success() => {
+                    self.with_breaker(endpoint, CircuitBreaker::record_success);
+                    return Ok(response);
+                }
+                Ok(response) => {
+                    let status = response.status();
+                    let retryable = status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error();
+                    // Any non-5xx answer proves the endpoint is up.
+                    if status.is_server_error() {
+                        self.with_breaker(endpoint, CircuitBreaker::record_failure);
+                    } else {
+                        self.with_breaker(endpoint, CircuitBreaker::record_success);
+                    }
+                    let delay = retry_after(response.headers()).unwrap_or_else(|| self.retry.backoff(attempt));
+                    if !retryable || attempt >= self.retry.max_retries || !fits_deadline(delay) {
+                        let body = response.text().await.unwrap_or_default();
+                        return Err(Box::new(TransportError::Status {
+                            endpoint: endpoint.to_string(),
+                            status: status.as_u16(),
+                            body,
+                        }));
+                    }
+                    warn!("LLM API {} returned {}; retrying in {:?}", endpoint, status, delay);
+                    delay
+                }
+                Err(e) if e.is_timeout() || e.is_connect() => {
+                    self.with_breaker(endpoint, CircuitBreaker::record_failure);
+                    let delay = self.retry.backoff(attempt);
+                    if attempt >= self.retry.max_retries || !fits_deadline(delay) {
+                        return Err(Box::new(e));
+                    }
+                    warn!("LLM API {} unreachable ({}); retrying in {:?}", endpoint, e, delay);
+                    delay
+                }
+                Err(e) => {
+                    self.with_breaker(endpoint, CircuitBreaker::record_failure);
+                    return Err(Box::new(e));
+                }
+            };
+
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::RequestOptions;
+    use serde_json::json;
+    use wiremock::matchers::method;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn transport(max_retries: u32, breaker_threshold: u32, breaker_cooldown: Duration) -> HttpTransport {
+        let retry = RetryPolicy { max_retries, base_delay: Duration::from_millis(1), max_delay: Duration::from_millis(5) };
+        HttpTransport::new(Client::new(), retry, Duration::from_secs(5), breaker_threshold, breaker_cooldown)
+    }
+
+    /// Answers `failures` requests with `failure`, then succeeds.
+    async fn flaky_server(failures: u64, failure: ResponseTemplate) -> MockServer {
+        let server = MockServer::start().await;
+        Mock::given(method("POST")).respond_with(failure).up_to_n_times(failures).with_priority(1).mount(&server).await;
+        Mock::given(method("POST")).respond_with(ResponseTemplate::new(200).set_body_json(json!({"ok": true}))).mount(&server).await;
+        server
+    }
+
+    async fn hits(server: &MockServer) -> usize {
+        server.received_requests().await.unwrap().len()
+    }
+
+    fn status_of(error: &(dyn Error + 'static)) -> Option<u16> {
+        match error.downcast_ref::<TransportError>() {
+            Some(TransportError::Status { status, .. }) => Some(*status),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn backoff_stays_under_exponential_ceiling() {
+        let retry = RetryPolicy { max_retries: 5, base_delay: Duration::from_millis(100), max_delay: Duration::from_millis(350) };
+        for _ in 0..50 {
+            assert!(retry.backoff(0) <= Duration::from_millis(100));
+            assert!(retry.backoff(1) <= Duration::from_millis(200));
+            assert!(retry.backoff(4) <= Duration::from_millis(350));
+        }
+    }
+
+    #[test]
+    fn retry_after_accepts_seconds_and_is_capped() {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, "3".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(3)));
+        headers.insert(RETRY_AFTER, "3600".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(MAX_RETRY_AFTER));
+        headers.insert(RETRY_AFTER, "soon".parse().unwrap());
+        assert_eq!(retry_after(&headers), None);
+    }
+
+    #[test]
+    fn breaker_lets_another_trial_through_when_one_is_lost() {
+        let mut breaker = CircuitBreaker::new(1, Duration::from_millis(20));
+        breaker.record_failure();
+        assert!(!breaker.allow());
+        std::thread::sleep(Duration::from_millis(25));
+        assert!(breaker.allow(), "cooldown elapsed, trial allowed");
+        assert!(!breaker.allow(), "only one trial at a time");
+        std::thread::sleep(Duration::from_millis(25));
+        assert!(breaker.allow(), "unreported trial written off");
+        breaker.record_success();
+        assert!(!breaker.is_open());
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors_until_success() {
+        let server = flaky_server(2, ResponseTemplate::new(503)).await;
+        let transport = transport(3, 5, Duration::from_secs(30));
+        let response = transport.post_json(&server.uri(), &json!({}), None, false).await.unwrap();
+        assert!(response.status().is_success());
+        assert_eq!(hits(&server).await, 3);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_retries() {
+        let server = flaky_server(10, ResponseTemplate::new(500)).await;
+        let transport = transport(2, 10, Duration::from_secs(30));
+        let error = transport.post_json(&server.uri(), &json!({}), None, false).await.unwrap_err();
+        assert_eq!(status_of(error.as_ref()), Some(500));
+        assert_eq!(hits(&server).await, 3);
+    }
+
+    #[tokio::test]
+    async fn client_errors_are_not_retried() {
+        let server = flaky_server(1, ResponseTemplate::new(400)).await;
+        let transport = transport(3, 5, Duration::from_secs(30));
+        let error = transport.post_json(&server.uri(), &json!({}), None, false).await.unwrap_err();
+        assert_eq!(status_of(error.as_ref()), Some(400));
+        assert_eq!(hits(&server).await, 1);
+    }
+
+    #[tokio::test]
+    async fn honours_retry_after() {
+        let server = flaky_server(1, ResponseTemplate::new(429).insert_header("Retry-After", "1")).await;
+        let transport = transport(3, 5, Duration::from_secs(30));
+        let started = Instant::now();
+        transport.post_json(&server.uri(), &json!({}), None, false).await.unwrap();
+        assert!(started.elapsed() >= Duration::from_secs(1));
+        assert_eq!(hits(&server).await, 2);
+    }
+
+    #[tokio::test]
+    async fn retry_after_past_the_deadline_returns_the_error() {
+        let server = flaky_server(1, ResponseTemplate::new(429).insert_header("Retry-After", "30")).await;
+        let transport = transport(3, 5, Duration::from_secs(30));
+        let options = RequestOptions::with_timeout(Duration::from_secs(2));
+        let started = Instant::now();
+        let result = options.run_stage(transport.post_json(&server.uri(), &json!({}), None, false)).await.unwrap();
+        assert_eq!(status_of(result.unwrap_err().as_ref()), Some(429));
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(hits(&server).await, 1);
+    }
+
+    #[tokio::test]
+    async fn breaker_opens_then_recovers_after_cooldown() {
+        let server = flaky_server(2, ResponseTemplate::new(500)).await;
+        let transport = transport(0, 2, Duration::from_millis(50));
+        let url = server.uri();
+        for _ in 0..2 {
+            transport.post_json(&url, &json!({}), None, false).await.unwrap_err();
+        }
+        assert!(transport.is_circuit_open(&url));
+        let error = transport.post_json(&url, &json!({}), None, false).await.unwrap_err();
+        assert!(matches!(error.downcast_ref::<TransportError>(), Some(TransportError::CircuitOpen { .. })));
+        assert_eq!(hits(&server).await, 2);
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        transport.post_json(&url, &json!({}), None, false).await.unwrap();
+        assert!(!transport.is_circuit_open(&url));
+    }
+}
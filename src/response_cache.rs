@@ -0,0 +1,178 @@
+use rocksdb::{IteratorMode, Options, DB};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+use crate::generation::GenerationParams;
+use crate::session::now_secs;
+
+/// Identifies a request for caching purposes: BLAKE3 of the backend,
+/// generation params, retrieved knowledge and final prompt, as built before
+/// HHTC compression.
+#[derive(Debug, Clone)]
+pub struct CacheKey {
+    key: String,
+}
+
+impl CacheKey {
+    pub fn new(backend: &str, final_prompt: &str, knowledge: &[String], params: &GenerationParams) -> Self {
+        let mut scope = blake3::Hasher::new();
+        scope.update(backend.as_bytes());
+        scope.update(&[0]);
+        scope.update(serde_json::to_string(params).unwrap_or_default().as_bytes());
+        for fact in knowledge {
+            scope.update(&[0]);
+            scope.update(fact.as_bytes());
+        }
+        let scope = scope.finalize().to_hex().to_string();
+
+        let mut key = blake3::Hasher::new();
+        key.update(scope.as_bytes());
+        key.update(final_prompt.as_bytes());
+
+        Self { key: key.finalize().to_hex().to_string() }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedResponse {
+    pub output: String,
+    /// Whether the output passed verification when it was cached.
+    pub verified: bool,
+    pub created_at: u64,
+}
+
+/// Responses keyed by final prompt and generation params, kept in RocksDB
+/// for `OPTIMA_CACHE_TTL_SECS`. Because retrieved EKF knowledge is part of
+/// every key, a change in what the EKF returns is a miss rather than a stale
+/// hit. Only byte-identical requests match; there is no semantic matching
+/// until a real sentence embedder is available.
+pub struct ResponseCache {
+    db: DB,
+    ttl_secs: u64,
+}
+
+impl ResponseCache {
+    /// Configured through `OPTIMA_RESPONSE_CACHE` (`off` or `exact`;
+    /// default `exact`) and `OPTIMA_CACHE_TTL_SECS` (default 3600). Returns
+    /// `None` when the cache is off.
+    pub fn from_env() -> Result<Option<Self>, Box<dyn Error>> {
+        match std::env::var("OPTIMA_RESPONSE_CACHE").unwrap_or_else(|_| "exact".to_string()).to_lowercase().as_str() {
+            "off" | "false" | "0" => return Ok(None),
+            "exact" => {}
+            "semantic" => return Err("semantic response caching is not implemented; use OPTIMA_RESPONSE_CACHE=exact".into()),
+            other => return Err(format!("Unknown OPTIMA_RESPONSE_CACHE mode '{}'", other).into()),
+        }
+        let ttl_secs = std::env::var("OPTIMA_CACHE_TTL_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(3600);
+        Ok(Some(Self::open(&cache_dir(), ttl_secs)?))
+    }
+
+    pub fn open(path: &Path, ttl_secs: u64) -> Result<Self, Box<dyn Error>> {
+        let mut opts = Options::default();
+        opts.create_if_missing(true);
+        let db = DB::open(&opts, path)?;
+        info!("Response cache initialized at: {:?}", path);
+
+        let cache = Self { db, ttl_secs };
+        cache.purge_expired()?;
+        Ok(cache)
+    }
+
+    fn is_expired(&self, created_at: u64) -> bool {
+        now_secs().saturating_sub(created_at) >= self.ttl_secs
+    }
+
+    pub fn get(&self, key: &CacheKey) -> Result<Option<CachedResponse>, Box<dyn Error>> {
+        self.read(&key.key)
+    }
+
+    pub fn put(&self, key: &CacheKey, output: &str, verified: bool) -> Result<(), Box<dyn Error>> {
+        let entry = CachedResponse { output: output.to_string(), verified, created_at: now_secs() };
+        self.db.put(&key.key, serde_json::to_vec(&entry)?)?;
+        Ok(())
+    }
+
+    fn read(&self, key: &str) -> Result<Option<CachedResponse>, Box<dyn Error>> {
+        let Some(bytes) = self.db.get(key)? else {
+            return Ok(None);
+        };
+        match serde_json::from_slice::<CachedResponse>(&bytes) {
+            Ok(entry) if !self.is_expired(entry.created_at) => Ok(Some(entry)),
+            Ok(_) => Ok(None),
+            Err(e) => {
+                warn!("Discarding unreadable cache entry {}: {}", key, e);
+                self.db.delete(key)?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Delete expired and unreadable entries.
+    pub fn purge_expired(&self) -> Result<usize, Box<dyn Error>> {
+        let mut expired = Vec::new();
+        for item in self.db.iterator(IteratorMode::Start) {
+            let (key, value) = item?;
+            match serde_json::from_slice::<CachedResponse>(&value) {
+                Ok(entry) if !self.is_expired(entry.created_at) => {}
+                _ => expired.push(key),
+            }
+        }
+        for key in &expired {
+            self.db.delete(key)?;
+        }
+        if !expired.is_empty() {
+            info!("Purged {} expired response cache entries", expired.len());
+        }
+        Ok(expired.len())
+    }
+}
+
+fn cache_dir() -> PathBuf {
+    let data_dir = std::env::var("OPTIMA_DATA_DIR").unwrap_or_else(|_| ".".to_string());
+    Path::new(&data_dir).join("response_cache")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(backend: &str, knowledge: &[&str], params: &GenerationParams) -> CacheKey {
+        let knowledge: Vec<String> = knowledge.iter().map(|fact| fact.to_string()).collect();
+        CacheKey::new(backend, "What is the capital of France?", &knowledge, params)
+    }
+
+    #[test]
+    fn hit_for_the_same_request_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ResponseCache::open(dir.path(), 3600).unwrap();
+        let params = GenerationParams::default();
+        cache.put(&key("generic", &["Paris is in France."], &params), "Paris.", true).unwrap();
+
+        let hit = cache.get(&key("generic", &["Paris is in France."], &params)).unwrap().unwrap();
+        assert_eq!(hit.output, "Paris.");
+        assert!(hit.verified);
+
+        let misses = [
+            key("generic", &["Paris is the capital."], &params),
+            key("generic", &[], &params),
+            key("generic", &["Paris is in France."], &GenerationParams { temperature: 0.1, ..params.clone() }),
+            key("openai", &["Paris is in France."], &params),
+        ];
+        for miss in &misses {
+            assert!(cache.get(miss).unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn expired_entries_miss_and_are_purged() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ResponseCache::open(dir.path(), 0).unwrap();
+        let key = key("generic", &[], &GenerationParams::default());
+        cache.put(&key, "Paris.", true).unwrap();
+
+        assert!(cache.get(&key).unwrap().is_none());
+        assert_eq!(cache.purge_expired().unwrap(), 1);
+        assert_eq!(cache.purge_expired().unwrap(), 0);
+    }
+}
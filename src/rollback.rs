@@ -0,0 +1,114 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+
+use crate::verifier::VerificationReport;
+
+/// How a rejected answer is retried. Policies accumulate: a later attempt
+/// keeps every adjustment made by the earlier ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RollbackPolicy {
+    /// Move to the next cascade tier. Repeats until the strongest tier is
+    /// reached, then the next policy takes over.
+    StrongerBackend,
+    /// Tell the model which facts its previous answer contradicted.
+    ExplicitFacts,
+    /// Cap the sampling temperature at `OPTIMA_ROLLBACK_TEMPERATURE`.
+    LowerTemperature,
+    /// Send the trimmed prompt without HHTC surrogate tokens.
+    NoCompression,
+    /// Raise the frequency penalty to `OPTIMA_LOOP_REPETITION_PENALTY`.
+    /// Applied straight away when the output loops, on backends that take
+    /// a frequency penalty.
+    RepetitionPenalty,
+    /// Re-prompt with the JSON Schema validation errors. Applied when the
+    /// output does not match the request's schema; not configurable.
+    SchemaRepair,
+}
+
+impl RollbackPolicy {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "stronger_backend" => Some(Self::StrongerBackend),
+            "explicit_facts" => Some(Self::ExplicitFacts),
+            "lower_temperature" => Some(Self::LowerTemperature),
+            "no_compression" => Some(Self::NoCompression),
+            "repetition_penalty" => Some(Self::RepetitionPenalty),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RollbackConfig {
+    pub policies: Vec<RollbackPolicy>,
+    /// Retries allowed after the first generation, escalations included.
+    pub max_attempts: u32,
+    pub temperature: f64,
+    /// Frequency penalty for `RepetitionPenalty`; `None` leaves looping
+    /// outputs truncated without regenerating.
+    pub repetition_penalty: Option<f64>,
+    /// Re-prompts with the validation errors when output does not match
+    /// the request's JSON Schema; not counted against `max_attempts`.
+    pub schema_retries: u32,
+}
+
+impl RollbackConfig {
+    /// Read `OPTIMA_ROLLBACK_POLICIES` (comma-separated, in order; default
+    /// `stronger_backend,explicit_facts,lower_temperature,no_compression`),
+    /// `OPTIMA_ROLLBACK_MAX_ATTEMPTS` (default 3),
+    /// `OPTIMA_ROLLBACK_TEMPERATURE` (default 0.1),
+    /// `OPTIMA_LOOP_REPETITION_PENALTY` (unset by default) and
+    /// `OPTIMA_SCHEMA_RETRIES` (default 2).
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        let spec = std::env::var("OPTIMA_ROLLBACK_POLICIES")
+            .unwrap_or_else(|_| "stronger_backend,explicit_facts,lower_temperature,no_compression".to_string());
+        let policies = spec
+            .split(',')
+            .filter(|name| !name.trim().is_empty())
+            .map(|name| RollbackPolicy::parse(name).ok_or_else(|| format!("Unknown rollback policy '{}'", name.trim())))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            policies,
+            max_attempts: std::env::var("OPTIMA_ROLLBACK_MAX_ATTEMPTS").ok().and_then(|v| v.parse().ok()).unwrap_or(3),
+            temperature: std::env::var("OPTIMA_ROLLBACK_TEMPERATURE").ok().and_then(|v| v.parse().ok()).unwrap_or(0.1),
+            repetition_penalty: std::env::var("OPTIMA_LOOP_REPETITION_PENALTY").ok().and_then(|v| v.parse().ok()),
+            schema_retries: std::env::var("OPTIMA_SCHEMA_RETRIES").ok().and_then(|v| v.parse().ok()).unwrap_or(2),
+        })
+    }
+}
+
+/// One generation in the rollback loop.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttemptRecord {
+    /// 0 for the first generation.
+    pub attempt: u32,
+    pub tier: String,
+    /// Policy applied for this attempt; `None` for the first generation.
+    pub policy: Option<RollbackPolicy>,
+    /// Why the verifier rejected this attempt; `None` if it passed or was
+    /// not verified.
+    pub rejected: Option<String>,
+}
+
+/// Every attempt was rejected by the verifier, or the request ran out of
+/// time or attempts while retrying.
+#[derive(Debug, Clone)]
+pub struct VerificationError {
+    pub attempts: Vec<AttemptRecord>,
+    /// The last rejected output, for callers that want to show it anyway.
+    pub last_output: String,
+    /// Claim-level verdicts for `last_output`, when there were facts to
+    /// check it against.
+    pub report: Option<VerificationReport>,
+}
+
+impl fmt::Display for VerificationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = self.attempts.last().and_then(|attempt| attempt.rejected.as_deref()).unwrap_or("unknown");
+        write!(f, "verification failed after {} attempt(s): {}", self.attempts.len(), reason)
+    }
+}
+
+impl Error for VerificationError {}
@@ -0,0 +1,312 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
+
+const BUILTIN_PACK: &str = include_str!("rules/default.toml");
+
+fn default_negation_window() -> usize {
+    50
+}
+
+fn default_negation_weight() -> f64 {
+    0.4
+}
+
+fn default_concepts_per_fact() -> usize {
+    3
+}
+
+fn default_antonym_weight() -> f64 {
+    0.5
+}
+
+/// Adds `weight` when the output matches `pattern` and, if `fact_pattern`
+/// is set, at least one fact matches it.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RegexRule {
+    pub name: String,
+    pub pattern: String,
+    #[serde(default)]
+    pub fact_pattern: Option<String>,
+    pub weight: f64,
+}
+
+/// A case a pack ships with; `optimacore verifier test-rules` checks that
+/// the loaded rules score it on the right side of the threshold.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RuleTest {
+    pub output: String,
+    pub facts: Vec<String>,
+    pub contradiction: bool,
+}
+
+/// One TOML or YAML rule pack. Packs with a `domain` only load when that
+/// domain is enabled.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RulePack {
+    pub name: String,
+    #[serde(default)]
+    pub domain: Option<String>,
+    /// Cue words that negate a fact's concept when they occur near it.
+    #[serde(default)]
+    pub negations: Vec<String>,
+    /// Byte distance within which a negation applies to a concept.
+    #[serde(default = "default_negation_window")]
+    pub negation_window: usize,
+    #[serde(default = "default_negation_weight")]
+    pub negation_weight: f64,
+    /// How many leading words of each fact count as its concepts.
+    #[serde(default = "default_concepts_per_fact")]
+    pub concepts_per_fact: usize,
+    /// `[word in a fact, its opposite in the output]`.
+    #[serde(default)]
+    pub antonyms: Vec<(String, String)>,
+    #[serde(default = "default_antonym_weight")]
+    pub antonym_weight: f64,
+    #[serde(default)]
+    pub regex: Vec<RegexRule>,
+    #[serde(default)]
+    pub tests: Vec<RuleTest>,
+    #[serde(skip)]
+    compiled: Vec<(Regex, Option<Regex>)>,
+}
+
+/// A rule that fired and what it added to the contradiction score.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuleHit {
+    pub pack: String,
+    pub rule: String,
+    pub weight: f64,
+}
+
+impl RulePack {
+    pub fn from_toml(text: &str) -> Result<Self, Box<dyn Error>> {
+        toml::from_str::<Self>(text)?.compile()
+    }
+
+    pub fn from_yaml(text: &str) -> Result<Self, Box<dyn Error>> {
+        serde_yaml::from_str::<Self>(text)?.compile()
+    }
+
+    /// Parse by extension: `.toml`, `.yaml` or `.yml`.
+    pub fn from_file(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let text = std::fs::read_to_string(path)?;
+        let pack = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => Self::from_toml(&text),
+            Some("yaml") | Some("yml") => Self::from_yaml(&text),
+            _ => return Err(format!("{:?} is not a .toml or .yaml rule pack", path).into()),
+        };
+        pack.map_err(|e| format!("Invalid rule pack {:?}: {}", path, e).into())
+    }
+
+    fn compile(mut self) -> Result<Self, Box<dyn Error>> {
+        self.compiled = self
+            .regex
+            .iter()
+            .map(|rule| {
+                let pattern = Regex::new(&rule.pattern)?;
+                let fact_pattern = rule.fact_pattern.as_deref().map(Regex::new).transpose()?;
+                Ok((pattern, fact_pattern))
+            })
+            .collect::<Result<_, regex::Error>>()
+            .map_err(|e| format!("rule pack '{}': {}", self.name, e))?;
+        Ok(self)
+    }
+
+    pub fn evaluate(&self, output: &str, facts: &[String]) -> Vec<RuleHit> {
+        let lower_output = output.to_lowercase();
+        let mut hits = Vec::new();
+        let mut hit = |rule: String, weight: f64| hits.push(RuleHit { pack: self.name.clone(), rule, weight });
+
+        for fact in facts {
+            let lower_fact = fact.to_lowercase();
+            let key_concepts = lower_fact
+                .split([' ', '.', ',', ';', '-'])
+                .take(self.concepts_per_fact)
+                .filter(|concept| concept.chars().count() >= 3);
+
+            for concept in key_concepts {
+                if !lower_output.contains(concept) {
+                    continue;
+                }
+                for negation in &self.negations {
+                    let mut near = 0;
+                    for (concept_idx, _) in lower_output.match_indices(concept) {
+                        near += lower_output
+                            .match_indices(negation.as_str())
+                            .filter(|(negation_idx, _)| concept_idx.abs_diff(*negation_idx) < self.negation_window)
+                            .count();
+                    }
+                    if near > 0 {
+                        hit(format!("negation '{}' near '{}'", negation, concept), near as f64 * self.negation_weight);
+                    }
+                }
+            }
+
+            for (word, opposite) in &self.antonyms {
+                if lower_fact.contains(word.as_str()) && lower_output.contains(opposite.as_str()) {
+                    hit(format!("antonym '{}' / '{}'", word, opposite), self.antonym_weight);
+                }
+            }
+        }
+
+        for (rule, (pattern, fact_pattern)) in self.regex.iter().zip(&self.compiled) {
+            let applies = fact_pattern.as_ref().is_none_or(|fact_pattern| facts.iter().any(|fact| fact_pattern.is_match(fact)));
+            if applies && pattern.is_match(output) {
+                hit(format!("regex '{}'", rule.name), rule.weight);
+            }
+        }
+        hits
+    }
+}
+
+/// The rule packs in effect: the built-in pack (or a replacement named
+/// `default`) plus every enabled pack from the rules directory.
+#[derive(Debug, Clone)]
+pub struct RuleSet {
+    packs: Vec<RulePack>,
+    builtin: bool,
+}
+
+impl RuleSet {
+    pub fn builtin() -> Self {
+        let pack = RulePack::from_toml(BUILTIN_PACK).expect("built-in rule pack is valid");
+        Self { packs: vec![pack], builtin: true }
+    }
+
+    /// Load every `.toml`/`.yaml`/`.yml` pack in `dir`, in file name order.
+    /// `domains` of `None` enables every domain pack. A missing directory
+    /// leaves only the built-in pack.
+    pub fn load(dir: &Path, domains: Option<&[String]>) -> Result<Self, Box<dyn Error>> {
+        let mut set = Self::builtin();
+        for path in rule_files(dir)? {
+            let pack = RulePack::from_file(&path)?;
+            if let (Some(domain), Some(domains)) = (&pack.domain, domains) {
+                if !domains.contains(domain) {
+                    continue;
+                }
+            }
+            if pack.name == "default" {
+                set.packs[0] = pack;
+                set.builtin = false;
+            } else {
+                set.packs.push(pack);
+            }
+        }
+        Ok(set)
+    }
+
+    pub fn packs(&self) -> &[RulePack] {
+        &self.packs
+    }
+
+    /// Only the built-in pack is loaded, so the Julia detector (which
+    /// hardcodes the same rules) can stand in for it.
+    pub fn is_builtin(&self) -> bool {
+        self.builtin && self.packs.len() == 1
+    }
+
+    pub fn evaluate(&self, output: &str, facts: &[String]) -> Vec<RuleHit> {
+        self.packs.iter().flat_map(|pack| pack.evaluate(output, facts)).collect()
+    }
+
+    /// Sum of every hit's weight, capped at 1.
+    pub fn score(&self, output: &str, facts: &[String]) -> f64 {
+        f64::min(self.evaluate(output, facts).iter().fold(0.0, |score, hit| score + hit.weight), 1.0)
+    }
+}
+
+fn rule_files(dir: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| matches!(path.extension().and_then(|ext| ext.to_str()), Some("toml" | "yaml" | "yml")))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// Modification time and size of every rule file; a change means reload.
+fn fingerprint(dir: &Path) -> Vec<(PathBuf, Option<SystemTime>, u64)> {
+    rule_files(dir)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|path| {
+            let metadata = std::fs::metadata(&path).ok();
+            let modified = metadata.as_ref().and_then(|metadata| metadata.modified().ok());
+            let len = metadata.map_or(0, |metadata| metadata.len());
+            (path, modified, len)
+        })
+        .collect()
+}
+
+/// The live rule set, swapped in place when the rules directory changes.
+pub struct RuleStore {
+    dir: PathBuf,
+    domains: Vec<String>,
+    current: RwLock<Arc<RuleSet>>,
+    fingerprint: Mutex<Vec<(PathBuf, Option<SystemTime>, u64)>>,
+}
+
+impl RuleStore {
+    /// Load packs from `OPTIMA_RULES_DIR` (default `./rules`), enabling the
+    /// domain packs listed in `OPTIMA_RULE_DOMAINS` (comma-separated).
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        let dir = PathBuf::from(std::env::var("OPTIMA_RULES_DIR").unwrap_or_else(|_| "./rules".to_string()));
+        let domains = std::env::var("OPTIMA_RULE_DOMAINS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|domain| domain.trim().to_string())
+            .filter(|domain| !domain.is_empty())
+            .collect::<Vec<_>>();
+        let set = RuleSet::load(&dir, Some(&domains))?;
+        info!("Loaded {} verifier rule pack(s) from {:?}", set.packs().len(), dir);
+        Ok(Self {
+            fingerprint: Mutex::new(fingerprint(&dir)),
+            current: RwLock::new(Arc::new(set)),
+            dir,
+            domains,
+        })
+    }
+
+    pub fn current(&self) -> Arc<RuleSet> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Reload if any rule file was added, removed or modified. A pack that
+    /// fails to parse leaves the previous rules in effect.
+    pub fn reload_if_changed(&self) -> bool {
+        let latest = fingerprint(&self.dir);
+        let mut seen = self.fingerprint.lock().unwrap();
+        if *seen == latest {
+            return false;
+        }
+        *seen = latest;
+        match RuleSet::load(&self.dir, Some(&self.domains)) {
+            Ok(set) => {
+                info!("Reloaded {} verifier rule pack(s) from {:?}", set.packs().len(), self.dir);
+                *self.current.write().unwrap() = Arc::new(set);
+                true
+            }
+            Err(e) => {
+                warn!("Keeping previous verifier rules: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Poll the rules directory every `interval`.
+    pub async fn watch(self: Arc<Self>, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            self.reload_if_changed();
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::info;
+
+use crate::pii::PiiMap;
+
+/// EKF record namespace under which sessions are persisted.
+pub const SESSION_NAMESPACE: &str = "session";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Turn {
+    pub prompt: String,
+    pub response: String,
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SessionStats {
+    pub turns: u64,
+    pub total_compression: f64,
+    pub reflections_trimmed: u64,
+}
+
+impl SessionStats {
+    pub fn avg_compression_ratio(&self) -> f64 {
+        if self.turns > 0 {
+            self.total_compression / self.turns as f64
+        } else {
+            0.0
+        }
+    }
+}
+
+/// A multi-turn conversation. Earlier turns form a stable, uncompressed
+/// prefix of every new prompt, so servers with prefix caching reuse their
+/// state; only the new prompt goes through HHTC.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub id: String,
+    /// With PII as placeholders, since sessions are persisted.
+    pub turns: Vec<Turn>,
+    /// The values behind the placeholders in `turns`. Never persisted, so a
+    /// session resumed after a restart keeps its placeholders but can no
+    /// longer restore them in answers.
+    #[serde(skip)]
+    pub pii: PiiMap,
+    pub created_at: u64,
+    pub last_active: u64,
+    pub stats: SessionStats,
+}
+
+impl Session {
+    fn new(id: String) -> Self {
+        let now = now_secs();
+        Self { id, turns: Vec::new(), pii: PiiMap::default(), created_at: now, last_active: now, stats: SessionStats::default() }
+    }
+
+    /// The conversation so far, in the form prepended to the next prompt.
+    pub fn transcript(&self) -> String {
+        self.turns
+            .iter()
+            .map(|turn| format!("User: {}\nAssistant: {}", turn.prompt, turn.response))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    pub fn record_turn(&mut self, prompt: &str, response: &str, compression_ratio: f64, reflection_trimmed: bool) {
+        let now = now_secs();
+        self.turns.push(Turn { prompt: prompt.to_string(), response: response.to_string(), timestamp: now });
+        self.last_active = now;
+        self.stats.turns += 1;
+        self.stats.total_compression += 1.0 - compression_ratio;
+        if reflection_trimmed {
+            self.stats.reflections_trimmed += 1;
+        }
+    }
+
+    pub fn is_expired(&self, idle_timeout: Duration, now: u64) -> bool {
+        now.saturating_sub(self.last_active) > idle_timeout.as_secs()
+    }
+}
+
+/// In-memory registry of live sessions with inactivity expiry.
+pub struct SessionManager {
+    sessions: HashMap<String, Session>,
+    idle_timeout: Duration,
+}
+
+impl SessionManager {
+    pub fn new() -> Self {
+        let idle_secs = std::env::var("OPTIMA_SESSION_IDLE_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(1800);
+        Self::with_idle_timeout(Duration::from_secs(idle_secs))
+    }
+
+    pub fn with_idle_timeout(idle_timeout: Duration) -> Self {
+        Self { sessions: HashMap::new(), idle_timeout }
+    }
+
+    pub fn idle_timeout(&self) -> Duration {
+        self.idle_timeout
+    }
+
+    pub fn create(&mut self) -> &Session {
+        let id = format!("{:032x}", rand::thread_rng().gen::<u128>());
+        info!("Created session {}", id);
+        self.sessions.entry(id.clone()).or_insert_with(|| Session::new(id))
+    }
+
+    pub fn insert(&mut self, session: Session) {
+        self.sessions.insert(session.id.clone(), session);
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Session> {
+        self.sessions.get(id)
+    }
+
+    pub fn get_mut(&mut self, id: &str) -> Option<&mut Session> {
+        self.sessions.get_mut(id)
+    }
+
+    pub fn remove(&mut self, id: &str) -> Option<Session> {
+        self.sessions.remove(id)
+    }
+
+    /// Drop every session idle for longer than the timeout and return their ids.
+    pub fn expire_idle(&mut self) -> Vec<String> {
+        let now = now_secs();
+        let idle_timeout = self.idle_timeout;
+        let expired: Vec<String> = self
+            .sessions
+            .values()
+            .filter(|session| session.is_expired(idle_timeout, now))
+            .map(|session| session.id.clone())
+            .collect();
+        for id in &expired {
+            info!("Session {} expired after inactivity", id);
+            self.sessions.remove(id);
+        }
+        expired
+    }
+}
+
+impl Default for SessionManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
@@ -0,0 +1,171 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use tracing::info;
+
+use crate::usage::{ModelPrice, TokenUsage};
+use crate::verifier::Verifier;
+
+/// How an answer to the optimized prompt compares with the answer to the
+/// same prompt sent without reflection trimming or HHTC compression.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShadowComparison {
+    /// With PII placeholders, as the model saw it.
+    pub baseline_output: String,
+    /// Jaccard similarity of the two answers' lowercased words. Word
+    /// overlap stands in for embedding similarity: `TinyBertEmbedder`'s
+    /// hash-seeded vectors say nothing about meaning.
+    pub similarity: f64,
+    /// Verifier score of each answer: 1 minus the contradiction score, or
+    /// 0 when the verifier would reject the answer.
+    pub optimized_quality: f64,
+    pub baseline_quality: f64,
+    /// `optimized_quality - baseline_quality`; negative when the
+    /// optimizations made the answer worse.
+    pub quality_delta: f64,
+    pub baseline_prompt_tokens: u64,
+    /// Prompt tokens the optimized request did without.
+    pub prompt_tokens_saved: u64,
+    /// What the extra baseline call cost.
+    pub cost_usd: f64,
+}
+
+impl ShadowComparison {
+    /// `prompt_tokens_saved` as a fraction of the baseline prompt.
+    pub fn token_savings(&self) -> f64 {
+        if self.baseline_prompt_tokens == 0 {
+            0.0
+        } else {
+            self.prompt_tokens_saved as f64 / self.baseline_prompt_tokens as f64
+        }
+    }
+}
+
+/// Sends a sampled fraction of requests a second time without OptimaCore's
+/// prompt transformations, to measure what they cost in answer quality.
+#[derive(Debug, Clone, Copy)]
+pub struct ShadowMode {
+    rate: f64,
+}
+
+impl ShadowMode {
+    /// Shadow `OPTIMA_SHADOW_RATE` of requests (0 to 1, default 0). `None`
+    /// when the rate is 0.
+    pub fn from_env() -> Option<Self> {
+        let rate: f64 = std::env::var("OPTIMA_SHADOW_RATE").ok().and_then(|v| v.parse().ok()).unwrap_or(0.0);
+        if rate <= 0.0 {
+            return None;
+        }
+        let rate = rate.min(1.0);
+        info!("Shadow mode: comparing {:.1}% of requests against unoptimized prompts", rate * 100.0);
+        Some(Self { rate })
+    }
+
+    /// Whether to shadow the current request.
+    pub fn sample(&self) -> bool {
+        rand::random::<f64>() < self.rate
+    }
+
+    /// Score `optimized` against the unoptimized call's `(output, usage)`.
+    pub async fn compare(
+        &self,
+        verifier: &Verifier,
+        ekf_knowledge: &[String],
+        optimized: &str,
+        (baseline, usage): (String, TokenUsage),
+        prompt_tokens_saved: u64,
+        price: Option<ModelPrice>,
+    ) -> ShadowComparison {
+        let similarity = word_similarity(optimized, &baseline);
+        let optimized_quality = quality(verifier, optimized, ekf_knowledge).await;
+        let baseline_quality = quality(verifier, &baseline, ekf_knowledge).await;
+        ShadowComparison {
+            baseline_output: baseline,
+            similarity,
+            optimized_quality,
+            baseline_quality,
+            quality_delta: optimized_quality - baseline_quality,
+            baseline_prompt_tokens: usage.prompt_tokens,
+            prompt_tokens_saved: prompt_tokens_saved.min(usage.prompt_tokens),
+            cost_usd: price.map_or(0.0, |price| price.cost(usage.prompt_tokens, usage.completion_tokens)),
+        }
+    }
+}
+
+async fn quality(verifier: &Verifier, output: &str, ekf_knowledge: &[String]) -> f64 {
+    let assessment = verifier.assess(output, ekf_knowledge).await;
+    if assessment.failure().is_some() {
+        0.0
+    } else {
+        1.0 - assessment.contradiction_score.unwrap_or(0.0)
+    }
+}
+
+fn word_similarity(a: &str, b: &str) -> f64 {
+    let words = |text: &str| -> HashSet<String> { text.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty()).map(str::to_lowercase).collect() };
+    let (a, b) = (words(a), words(b));
+    let union = a.union(&b).count();
+    if union == 0 {
+        1.0
+    } else {
+        a.intersection(&b).count() as f64 / union as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn comparison(baseline_prompt_tokens: u64, prompt_tokens_saved: u64) -> ShadowComparison {
+        ShadowComparison {
+            baseline_output: String::new(),
+            similarity: 1.0,
+            optimized_quality: 1.0,
+            baseline_quality: 1.0,
+            quality_delta: 0.0,
+            baseline_prompt_tokens,
+            prompt_tokens_saved,
+            cost_usd: 0.0,
+        }
+    }
+
+    #[test]
+    fn token_savings_is_a_share_of_the_baseline_prompt() {
+        assert_eq!(comparison(200, 50).token_savings(), 0.25);
+        assert_eq!(comparison(0, 0).token_savings(), 0.0);
+    }
+
+    #[test]
+    fn word_similarity_is_jaccard_over_lowercased_words() {
+        assert_eq!(word_similarity("The capital is Paris.", "paris is the CAPITAL"), 1.0);
+        // {the, capital, is, paris} vs {the, capital, is, lyon}: 3 of 5.
+        assert_eq!(word_similarity("The capital is Paris.", "The capital is Lyon."), 0.6);
+        assert_eq!(word_similarity("Paris", "Berlin"), 0.0);
+        assert_eq!(word_similarity("", "..."), 1.0);
+    }
+
+    #[tokio::test]
+    async fn compare_scores_both_answers() {
+        let verifier = Verifier::new().unwrap();
+        let shadow = ShadowMode { rate: 1.0 };
+        let price = ModelPrice { input_per_million: 1.0, output_per_million: 2.0 };
+        let baseline = ("I'm sorry, but I can't answer that.".to_string(), TokenUsage::reported(1000, 500));
+
+        let compared = shadow.compare(&verifier, &[], "The capital of France is Paris.", baseline, 400, Some(price)).await;
+        assert_eq!(compared.optimized_quality, 1.0);
+        assert_eq!(compared.baseline_quality, 0.0);
+        assert_eq!(compared.quality_delta, 1.0);
+        assert!(compared.similarity < 0.2, "{}", compared.similarity);
+        assert_eq!(compared.token_savings(), 0.4);
+        assert!((compared.cost_usd - 0.002).abs() < 1e-12);
+    }
+
+    #[tokio::test]
+    async fn savings_never_exceed_the_baseline_prompt() {
+        let verifier = Verifier::new().unwrap();
+        let baseline = ("Paris.".to_string(), TokenUsage::reported(10, 2));
+        let compared = ShadowMode { rate: 1.0 }.compare(&verifier, &[], "Paris.", baseline, 25, None).await;
+        assert_eq!(compared.prompt_tokens_saved, 10);
+        assert_eq!(compared.similarity, 1.0);
+        assert_eq!(compared.cost_usd, 0.0);
+    }
+}
@@ -0,0 +1,484 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::core::OptimaStats;
+use crate::pii::PiiRedaction;
+use crate::policy::{PolicyAction, PolicyDecision};
+use crate::session::now_secs;
+use crate::shadow::ShadowComparison;
+
+const HOUR_SECS: u64 = 3600;
+const DAY_SECS: u64 = 24 * HOUR_SECS;
+const HOURLY_RETENTION: usize = 7 * 24;
+const DAILY_RETENTION: usize = 365;
+
+/// Raw, additive counters. Averages are derived on demand so that counters
+/// from different buckets or processes can be summed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StatsCounters {
+    pub requests: u64,
+    pub total_compression: f64,
+    pub reflections_trimmed: u64,
+    pub total_gpu_utilization: f64,
+    pub total_bandwidth_saved: f64,
+    /// Requests that had GPU metrics; `total_gpu_utilization` is over these.
+    #[serde(default)]
+    pub gpu_samples: u64,
+    #[serde(default)]
+    pub cache_hits: u64,
+    #[serde(default)]
+    pub escalations: u64,
+    #[serde(default)]
+    pub total_cost_usd: f64,
+    #[serde(default)]
+    pub total_saved_usd: f64,
+    #[serde(default)]
+    pub answers_by_tier: BTreeMap<String, u64>,
+    #[serde(default)]
+    pub policy_blocks: u64,
+    #[serde(default)]
+    pub policy_redactions: u64,
+    #[serde(default)]
+    pub policy_flags: u64,
+    /// Content policy decisions of any action, by category.
+    #[serde(default)]
+    pub policy_by_category: BTreeMap<String, u64>,
+    /// Distinct PII values replaced with placeholders.
+    #[serde(default)]
+    pub pii_redactions: u64,
+    #[serde(default)]
+    pub pii_by_kind: BTreeMap<String, u64>,
+    /// Requests also sent unoptimized by shadow mode.
+    #[serde(default)]
+    pub shadow_samples: u64,
+    #[serde(default)]
+    pub total_shadow_similarity: f64,
+    #[serde(default)]
+    pub total_shadow_quality_delta: f64,
+    /// Sum of the fraction of prompt tokens saved on shadowed requests.
+    #[serde(default)]
+    pub total_shadow_token_savings: f64,
+    #[serde(default)]
+    pub shadow_cost_usd: f64,
+}
+
+/// Per-request measurements fed into the counters.
+#[derive(Debug, Clone, Default)]
+pub struct RequestSample {
+    pub compression_ratio: f64,
+    pub reflection_trimmed: bool,
+    /// `None` when GPU metrics were unavailable.
+    pub gpu_utilization: Option<f64>,
+    pub bandwidth_saved: f64,
+    pub cache_hit: bool,
+    pub escalations: u32,
+    /// Cascade tier that answered, if the LLM was called.
+    pub answered_by: Option<String>,
+    pub cost_usd: f64,
+    /// Baseline cost minus actual cost.
+    pub saved_usd: f64,
+    pub policy: Vec<PolicyDecision>,
+    pub pii: Vec<PiiRedaction>,
+}
+
+impl StatsCounters {
+    pub fn record(&mut self, sample: &RequestSample) {
+        self.requests += 1;
+        self.total_compression += 1.0 - sample.compression_ratio;
+        if sample.reflection_trimmed {
+            self.reflections_trimmed += 1;
+        }
+        if let Some(utilization) = sample.gpu_utilization {
+            self.total_gpu_utilization += utilization;
+            self.gpu_samples += 1;
+        }
+        self.total_bandwidth_saved += sample.bandwidth_saved;
+        if sample.cache_hit {
+            self.cache_hits += 1;
+        }
+        self.escalations += sample.escalations as u64;
+        self.total_cost_usd += sample.cost_usd;
+        self.total_saved_usd += sample.saved_usd;
+        if let Some(tier) = &sample.answered_by {
+            *self.answers_by_tier.entry(tier.clone()).or_insert(0) += 1;
+        }
+        for decision in &sample.policy {
+            match decision.action {
+                PolicyAction::Block => self.policy_blocks += 1,
+                PolicyAction::Redact => self.policy_redactions += 1,
+                PolicyAction::Flag => self.policy_flags += 1,
+            }
+            *self.policy_by_category.entry(decision.category.clone()).or_insert(0) += 1;
+        }
+        self.pii_redactions += sample.pii.len() as u64;
+        for redaction in &sample.pii {
+            *self.pii_by_kind.entry(redaction.kind.clone()).or_insert(0) += 1;
+        }
+    }
+
+    /// Shadow comparisons finish after their request was recorded, so they
+    /// are counted separately.
+    pub fn record_shadow(&mut self, shadow: &ShadowComparison) {
+        self.shadow_samples += 1;
+        self.total_shadow_similarity += shadow.similarity;
+        self.total_shadow_quality_delta += shadow.quality_delta;
+        self.total_shadow_token_savings += shadow.token_savings();
+        self.shadow_cost_usd += shadow.cost_usd;
+    }
+
+    pub fn merge(&mut self, other: &StatsCounters) {
+        self.requests += other.requests;
+        self.total_compression += other.total_compression;
+        self.reflections_trimmed += other.reflections_trimmed;
+        self.total_gpu_utilization += other.total_gpu_utilization;
+        self.gpu_samples += other.gpu_samples;
+        self.total_bandwidth_saved += other.total_bandwidth_saved;
+        self.cache_hits += other.cache_hits;
+        self.escalations += other.escalations;
+        self.total_cost_usd += other.total_cost_usd;
+        self.total_saved_usd += other.total_saved_usd;
+        for (tier, count) in &other.answers_by_tier {
+            *self.answers_by_tier.entry(tier.clone()).or_insert(0) += count;
+        }
+        self.policy_blocks += other.policy_blocks;
+        self.policy_redactions += other.policy_redactions;
+        self.policy_flags += other.policy_flags;
+        for (category, count) in &other.policy_by_category {
+            *self.policy_by_category.entry(category.clone()).or_insert(0) += count;
+        }
+        self.pii_redactions += other.pii_redactions;
+        for (kind, count) in &other.pii_by_kind {
+            *self.pii_by_kind.entry(kind.clone()).or_insert(0) += count;
+        }
+        self.shadow_samples += other.shadow_samples;
+        self.total_shadow_similarity += other.total_shadow_similarity;
+        self.total_shadow_quality_delta += other.total_shadow_quality_delta;
+        self.total_shadow_token_savings += other.total_shadow_token_savings;
+        self.shadow_cost_usd += other.shadow_cost_usd;
+    }
+
+    pub fn summary(&self) -> OptimaStats {
+        let average = |total: f64| if self.requests > 0 { total / self.requests as f64 } else { 0.0 };
+        let shadow_average = |total: f64| if self.shadow_samples > 0 { total / self.shadow_samples as f64 } else { 0.0 };
+        OptimaStats {
+            total_requests: self.requests,
+            avg_compression_ratio: average(self.total_compression),
+            reflections_trimmed: self.reflections_trimmed,
+            avg_gpu_utilization: match self.gpu_samples {
+                // Counters written before `gpu_samples` existed.
+                0 => average(self.total_gpu_utilization),
+                samples => self.total_gpu_utilization / samples as f64,
+            },
+            total_bandwidth_saved: self.total_bandwidth_saved,
+            cache_hits: self.cache_hits,
+            escalations: self.escalations,
+            total_cost_usd: self.total_cost_usd,
+            total_saved_usd: self.total_saved_usd,
+            answers_by_tier: self.answers_by_tier.clone(),
+            policy_blocks: self.policy_blocks,
+            policy_redactions: self.policy_redactions,
+            policy_flags: self.policy_flags,
+            policy_by_category: self.policy_by_category.clone(),
+            pii_redactions: self.pii_redactions,
+            pii_by_kind: self.pii_by_kind.clone(),
+            shadow_samples: self.shadow_samples,
+            avg_shadow_similarity: shadow_average(self.total_shadow_similarity),
+            avg_shadow_quality_delta: shadow_average(self.total_shadow_quality_delta),
+            avg_shadow_token_savings: shadow_average(self.total_shadow_token_savings),
+            shadow_cost_usd: self.shadow_cost_usd,
+            endpoint_stats: Vec::new(),
+        }
+    }
+}
+
+/// All-time totals plus hourly and daily rollups keyed by bucket start
+/// (unix seconds, UTC).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StatsSnapshot {
+    pub totals: StatsCounters,
+    pub hourly: BTreeMap<u64, StatsCounters>,
+    pub daily: BTreeMap<u64, StatsCounters>,
+}
+
+impl StatsSnapshot {
+    pub fn record(&mut self, sample: &RequestSample, now: u64) {
+        self.totals.record(sample);
+        self.hourly.entry(now - now % HOUR_SECS).or_default().record(sample);
+        self.daily.entry(now - now % DAY_SECS).or_default().record(sample);
+        Self::prune(&mut self.hourly, HOURLY_RETENTION);
+        Self::prune(&mut self.daily, DAILY_RETENTION);
+    }
+
+    pub fn record_shadow(&mut self, shadow: &ShadowComparison, now: u64) {
+        self.totals.record_shadow(shadow);
+        self.hourly.entry(now - now % HOUR_SECS).or_default().record_shadow(shadow);
+        self.daily.entry(now - now % DAY_SECS).or_default().record_shadow(shadow);
+        Self::prune(&mut self.hourly, HOURLY_RETENTION);
+        Self::prune(&mut self.daily, DAILY_RETENTION);
+    }
+
+    /// Combine stats from another worker process into this one.
+    pub fn merge(&mut self, other: &StatsSnapshot) {
+        self.totals.merge(&other.totals);
+        for (bucket, counters) in &other.hourly {
+            self.hourly.entry(*bucket).or_default().merge(counters);
+        }
+        for (bucket, counters) in &other.daily {
+            self.daily.entry(*bucket).or_default().merge(counters);
+        }
+        Self::prune(&mut self.hourly, HOURLY_RETENTION);
+        Self::prune(&mut self.daily, DAILY_RETENTION);
+    }
+
+    fn prune(buckets: &mut BTreeMap<u64, StatsCounters>, keep: usize) {
+        while buckets.len() > keep {
+            buckets.pop_first();
+        }
+    }
+}
+
+/// Stats for this worker process, flushed periodically to
+/// `$OPTIMA_DATA_DIR/stats/<worker-id>.json`.
+pub struct StatsStore {
+    path: PathBuf,
+    snapshot: StatsSnapshot,
+    flush_interval: Duration,
+    last_flush: Instant,
+    dirty: bool,
+}
+
+impl StatsStore {
+    pub fn new() -> Self {
+        let worker_id = worker_id();
+        let flush_secs = std::env::var("OPTIMA_STATS_FLUSH_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(60);
+        let path = stats_dir().join(format!("{}.json", worker_id));
+
+        let snapshot = match Self::read_snapshot(&path) {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                warn!("Could not load persisted stats from {:?}, starting fresh: {}", path, e);
+                StatsSnapshot::default()
+            }
+        };
+
+        Self {
+            path,
+            snapshot,
+            flush_interval: Duration::from_secs(flush_secs),
+            last_flush: Instant::now(),
+            dirty: false,
+        }
+    }
+
+    pub fn snapshot(&self) -> &StatsSnapshot {
+        &self.snapshot
+    }
+
+    pub fn record(&mut self, sample: &RequestSample) {
+        self.snapshot.record(sample, now_secs());
+        self.mark_dirty();
+    }
+
+    pub fn record_shadow(&mut self, shadow: &ShadowComparison) {
+        self.snapshot.record_shadow(shadow, now_secs());
+        self.mark_dirty();
+    }
+
+    /// Flush once `flush_interval` has passed since the last flush.
+    fn mark_dirty(&mut self) {
+        self.dirty = true;
+        if self.last_flush.elapsed() >= self.flush_interval {
+            if let Err(e) = self.flush() {
+                warn!("Failed to persist stats to {:?}: {}", self.path, e);
+            }
+        }
+    }
+
+    pub fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.last_flush = Instant::now();
+        if !self.dirty {
+            return Ok(());
+        }
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let tmp_path = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, serde_json::to_vec_pretty(&self.snapshot)?)?;
+        std::fs::rename(&tmp_path, &self.path)?;
+        self.dirty = false;
+        info!("Persisted stats to {:?}", self.path);
+        Ok(())
+    }
+
+    /// Load and merge the stats of every worker in the data directory. A
+    /// file that can't be read is skipped.
+    pub fn load_merged() -> Result<StatsSnapshot, Box<dyn Error>> {
+        let mut merged = StatsSnapshot::default();
+        let dir = stats_dir();
+        if !dir.exists() {
+            return Ok(merged);
+        }
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                match Self::read_snapshot(&path) {
+                    Ok(snapshot) => merged.merge(&snapshot),
+                    Err(e) => warn!("Skipping unreadable stats file {:?}: {}", path, e),
+                }
+            }
+        }
+        Ok(merged)
+    }
+
+    fn read_snapshot(path: &Path) -> Result<StatsSnapshot, Box<dyn Error>> {
+        if !path.exists() {
+            return Ok(StatsSnapshot::default());
+        }
+        Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+    }
+}
+
+impl Drop for StatsStore {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            warn!("Failed to persist stats to {:?}: {}", self.path, e);
+        }
+    }
+}
+
+impl Default for StatsStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// `OPTIMA_WORKER_ID`, or the hostname. The id is stable across restarts,
+/// so a worker picks its own totals back up and one-off CLI runs share a
+/// file. Several workers on one host sharing a data directory need
+/// distinct ids: set `OPTIMA_WORKER_ID`, or `OPTIMA_WORKER_ID_PER_PROCESS=1`
+/// to append the pid (each process then leaves its own files behind).
+pub fn worker_id() -> String {
+    let id = std::env::var("OPTIMA_WORKER_ID").ok().filter(|id| !id.is_empty()).unwrap_or_else(|| {
+        std::env::var("HOSTNAME")
+            .ok()
+            .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| "localhost".to_string())
+    });
+    let per_process = std::env::var("OPTIMA_WORKER_ID_PER_PROCESS").is_ok_and(|v| v == "1" || v.eq_ignore_ascii_case("true"));
+    if per_process {
+        format!("{}-{}", id, std::process::id())
+    } else {
+        id
+    }
+}
+
+fn stats_dir() -> PathBuf {
+    let data_dir = std::env::var("OPTIMA_DATA_DIR").unwrap_or_else(|_| ".".to_string());
+    Path::new(&data_dir).join("stats")
+}
+
+/// Format a bucket start as `YYYY-MM-DD HH:00` (UTC).
+pub fn format_bucket(secs: u64) -> String {
+    // Civil-from-days conversion (Howard Hinnant's algorithm).
+    let days = (secs / DAY_SECS) as i64;
+    let hour = (secs % DAY_SECS) / HOUR_SECS;
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02} {:02}:00", year, month, day, hour)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gpu_utilization_averages_only_sampled_requests() {
+        let mut counters = StatsCounters::default();
+        counters.record(&RequestSample { gpu_utilization: Some(80.0), ..RequestSample::default() });
+        counters.record(&RequestSample { gpu_utilization: None, ..RequestSample::default() });
+        counters.record(&RequestSample { gpu_utilization: Some(40.0), ..RequestSample::default() });
+        let summary = counters.summary();
+        assert_eq!(summary.total_requests, 3);
+        assert_eq!(summary.avg_gpu_utilization, 60.0);
+    }
+
+    #[test]
+    fn merge_keeps_gpu_sample_counts() {
+        let mut a = StatsCounters::default();
+        a.record(&RequestSample { gpu_utilization: Some(10.0), ..RequestSample::default() });
+        let mut b = StatsCounters::default();
+        b.record(&RequestSample::default());
+        a.merge(&b);
+        assert_eq!(a.gpu_samples, 1);
+        assert_eq!(a.summary().avg_gpu_utilization, 10.0);
+    }
+
+    fn shadow(similarity: f64, quality_delta: f64, prompt_tokens_saved: u64) -> ShadowComparison {
+        ShadowComparison {
+            baseline_output: String::new(),
+            similarity,
+            optimized_quality: 1.0 + quality_delta.min(0.0),
+            baseline_quality: 1.0 - quality_delta.max(0.0),
+            quality_delta,
+            baseline_prompt_tokens: 100,
+            prompt_tokens_saved,
+            cost_usd: 0.01,
+        }
+    }
+
+    #[test]
+    fn shadow_comparisons_do_not_count_as_requests() {
+        let mut snapshot = StatsSnapshot::default();
+        snapshot.record(&RequestSample::default(), 0);
+        snapshot.record_shadow(&shadow(0.5, -0.1, 25), 0);
+        let summary = snapshot.totals.summary();
+        assert_eq!(summary.total_requests, 1);
+        assert_eq!(summary.shadow_samples, 1);
+        assert_eq!(snapshot.hourly[&0].shadow_samples, 1);
+    }
+
+    #[test]
+    fn shadow_averages_are_over_shadowed_requests() {
+        let mut counters = StatsCounters::default();
+        for _ in 0..4 {
+            counters.record(&RequestSample::default());
+        }
+        counters.record_shadow(&shadow(0.5, -0.25, 20));
+        let mut other = StatsCounters::default();
+        other.record_shadow(&shadow(1.0, 0.5, 40));
+        counters.merge(&other);
+
+        let summary = counters.summary();
+        assert_eq!(summary.shadow_samples, 2);
+        assert_eq!(summary.avg_shadow_similarity, 0.75);
+        assert_eq!(summary.avg_shadow_quality_delta, 0.125);
+        assert!((summary.avg_shadow_token_savings - 0.3).abs() < 1e-12);
+        assert!((summary.shadow_cost_usd - 0.02).abs() < 1e-12);
+    }
+
+    #[test]
+    fn shadow_records_are_flushed_on_the_interval() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("worker.json");
+        let mut store = StatsStore { path: path.clone(), snapshot: StatsSnapshot::default(), flush_interval: Duration::ZERO, last_flush: Instant::now(), dirty: false };
+        store.record_shadow(&shadow(1.0, 0.0, 10));
+        let flushed = StatsStore::read_snapshot(&path).unwrap();
+        assert_eq!(flushed.totals.shadow_samples, 1);
+    }
+}
@@ -0,0 +1,146 @@
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::{mpsc, Mutex};
+use tracing::warn;
+
+use crate::llm_backend::TokenStream;
+use crate::pii::PiiRestorer;
+use crate::request::RequestOptions;
+use crate::verifier::Verifier;
+
+/// Events delivered to the caller of `OptimaCore::process_request_stream`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum StreamEvent {
+    /// A piece of generated text, in arrival order.
+    Chunk(String),
+    /// Incremental verification found a contradiction; no more chunks follow.
+    /// Chunks are forwarded before the sentences in them are verified, so
+    /// the client has already received some or all of the offending text
+    /// and should discard what it displayed. `partial_output` is everything
+    /// generated up to the abort, offending sentence included.
+    Aborted { contradiction_score: f64, partial_output: String },
+    /// The backend stream failed, or the request was cancelled or timed out.
+    Failed(String),
+    /// The stream finished. `verified` is true when the full output passed
+    /// the contradiction check.
+    Done { output: String, verified: bool },
+}
+
+/// Forward backend chunks to `tx`, verifying each run of complete sentences
+/// once at least `verify_every` new bytes have arrived, and whatever is left
+/// at the end. Stops early when the receiver is dropped. Verification sees
+/// the output with PII placeholders; the caller gets it with the original
+/// values restored. Returns the raw generated text, however the stream
+/// ended, so the caller can account for the tokens it was billed for, and
+/// the final event (`Done`, `Aborted` or `Failed`) for the caller to send
+/// once it has; `None` when the receiver is gone.
+pub(crate) async fn drive_stream(
+    mut tokens: TokenStream,
+    verifier: Arc<Mutex<Verifier>>,
+    ekf_knowledge: Vec<String>,
+    options: RequestOptions,
+    verify_every: usize,
+    mut restorer: PiiRestorer,
+    tx: mpsc::Sender<StreamEvent>,
+) -> (String, Option<StreamEvent>) {
+    let mut output = String::new();
+    let mut checked_len = 0;
+    let mut verified = false;
+
+    loop {
+        let next = match options.run_stage(tokens.next()).await {
+            Ok(next) => next,
+            Err(interrupted) => {
+                return (output, Some(StreamEvent::Failed(interrupted.to_string())));
+            }
+        };
+        match next {
+            Some(Ok(chunk)) => {
+                output.push_str(&chunk);
+                let visible = restorer.push(&chunk);
+                if !visible.is_empty() && tx.send(StreamEvent::Chunk(visible)).await.is_err() {
+                    return (output, None);
+                }
+                if output.len() - checked_len < verify_every {
+                    continue;
+                }
+                let Some(end) = last_sentence_end(&output[checked_len..]) else {
+                    continue;
+                };
+                let sentences = &output[checked_len..checked_len + end];
+                checked_len += end;
+                match check(&verifier, sentences, &ekf_knowledge).await {
+                    Check::Failed(score) => {
+                        let partial_output = restorer.restore(&output);
+                        return (output, Some(StreamEvent::Aborted { contradiction_score: score, partial_output }));
+                    }
+                    Check::Passed => verified = true,
+                    Check::Skipped => {}
+                }
+            }
+            Some(Err(e)) => {
+                return (output, Some(StreamEvent::Failed(e.to_string())));
+            }
+            None => break,
+        }
+    }
+
+    if !output[checked_len..].trim().is_empty() {
+        match check(&verifier, &output[checked_len..], &ekf_knowledge).await {
+            Check::Failed(score) => {
+                let partial_output = restorer.restore(&output);
+                return (output, Some(StreamEvent::Aborted { contradiction_score: score, partial_output }));
+            }
+            Check::Passed => verified = true,
+            Check::Skipped => verified = false,
+        }
+    }
+    let rest = restorer.finish();
+    if !rest.is_empty() && tx.send(StreamEvent::Chunk(rest)).await.is_err() {
+        return (output, None);
+    }
+    let restored = restorer.restore(&output);
+    (output, Some(StreamEvent::Done { output: restored, verified }))
+}
+
+/// Byte length of the complete sentences at the start of `text`: up to the
+/// last `.`, `!`, `?` or newline that is followed by whitespace, so decimals
+/// and a sentence still being generated are left for the next check.
+fn last_sentence_end(text: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    (1..bytes.len()).rev().find(|&i| bytes[i].is_ascii_whitespace() && matches!(bytes[i - 1], b'.' | b'!' | b'?' | b'\n'))
+}
+
+enum Check {
+    Passed,
+    Failed(f64),
+    Skipped,
+}
+
+async fn check(verifier: &Mutex<Verifier>, output: &str, ekf_knowledge: &[String]) -> Check {
+    if ekf_knowledge.is_empty() {
+        return Check::Skipped;
+    }
+    let verifier = verifier.lock().await;
+    let score = verifier.contradiction_score(output, ekf_knowledge).await;
+    if score > verifier.contradiction_threshold() {
+        warn!("Streaming verification failed (score: {:.2}). Aborting stream.", score);
+        Check::Failed(score)
+    } else {
+        Check::Passed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::last_sentence_end;
+
+    #[test]
+    fn sentence_end_skips_unfinished_sentences_and_decimals() {
+        assert_eq!(last_sentence_end("Paris is in France. It has 2.1 million"), Some(19));
+        assert_eq!(last_sentence_end("Inflation was 3.5"), None);
+        assert_eq!(last_sentence_end("Done!\n"), Some(5));
+        assert_eq!(last_sentence_end(""), None);
+    }
+}
@@ -0,0 +1,206 @@
+use jsonschema::Validator;
+use serde_json::Value;
+use std::error::Error;
+use std::fmt;
+
+/// A JSON Schema a request's output must satisfy, compiled once.
+pub struct OutputSchema {
+    schema: Value,
+    validator: Validator,
+}
+
+impl fmt::Debug for OutputSchema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OutputSchema").field("schema", &self.schema).finish()
+    }
+}
+
+/// Output that parsed and matched the schema.
+#[derive(Debug, Clone)]
+pub struct StructuredOutput {
+    pub value: Value,
+    /// The JSON text that was parsed: the output itself, or its repair.
+    pub text: String,
+    pub repaired: bool,
+}
+
+impl OutputSchema {
+    pub fn new(schema: Value) -> Result<Self, Box<dyn Error>> {
+        let validator = jsonschema::validator_for(&schema).map_err(|e| format!("Invalid JSON Schema: {}", e))?;
+        Ok(Self { schema, validator })
+    }
+
+    pub fn schema(&self) -> &Value {
+        &self.schema
+    }
+
+    /// Appended to the prompt so the model knows the expected shape.
+    pub fn instruction(&self) -> String {
+        format!("Reply with only a JSON value that matches this JSON Schema, without any other text:\n{}", self.schema)
+    }
+
+    /// Parse `output` and validate it, falling back to `repair_json`.
+    /// On failure, the errors for the repaired text are returned.
+    pub fn check(&self, output: &str) -> Result<StructuredOutput, Vec<String>> {
+        if let Ok(value) = serde_json::from_str::<Value>(output.trim()) {
+            if self.validator.is_valid(&value) {
+                return Ok(StructuredOutput { value, text: output.trim().to_string(), repaired: false });
+            }
+        }
+        let repaired = repair_json(output);
+        match serde_json::from_str::<Value>(&repaired) {
+            Ok(value) => {
+                let errors: Vec<String> = self
+                    .validator
+                    .iter_errors(&value)
+                    .map(|error| {
+                        let path = error.instance_path.to_string();
+                        if path.is_empty() {
+                            error.to_string()
+                        } else {
+                            format!("{}: {}", path, error)
+                        }
+                    })
+                    .collect();
+                if errors.is_empty() {
+                    Ok(StructuredOutput { value, text: repaired, repaired: true })
+                } else {
+                    Err(errors)
+                }
+            }
+            Err(e) => Err(vec![format!("not valid JSON: {}", e)]),
+        }
+    }
+}
+
+/// Cheap fixes for near-miss JSON: markdown code fences, prose around the
+/// value, trailing commas, and unclosed strings, arrays and objects.
+pub fn repair_json(text: &str) -> String {
+    let mut text = text.trim();
+    if let Some(fenced) = text.strip_prefix("```") {
+        let body = fenced.split_once('\n').map_or("", |(_, body)| body);
+        text = body.trim_end().strip_suffix("```").unwrap_or(body).trim();
+    }
+    let Some(start) = text.find(['{', '[']) else {
+        return text.to_string();
+    };
+
+    let mut repaired = String::with_capacity(text.len());
+    let mut closers = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    for c in text[start..].chars() {
+        if in_string {
+            repaired.push(c);
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' => closers.push('}'),
+            '[' => closers.push(']'),
+            '}' | ']' => {
+                drop_trailing_comma(&mut repaired);
+                closers.pop();
+            }
+            _ => {}
+        }
+        repaired.push(c);
+        if closers.is_empty() {
+            // The top-level value is complete; ignore whatever follows.
+            return repaired;
+        }
+    }
+
+    if in_string {
+        if escaped {
+            repaired.pop();
+        }
+        repaired.push('"');
+    }
+    drop_trailing_comma(&mut repaired);
+    while let Some(closer) = closers.pop() {
+        repaired.push(closer);
+    }
+    repaired
+}
+
+fn drop_trailing_comma(json: &mut String) {
+    let trimmed = json.trim_end().len();
+    if json[..trimmed].ends_with(',') {
+        json.truncate(trimmed - 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// (model output, repaired text).
+    const REPAIR_CASES: &[(&str, &str)] = &[
+        ("```json\n{\"a\": 1}\n```", r#"{"a": 1}"#),
+        ("```\n[1, 2]\n```", "[1, 2]"),
+        (r#"Sure! Here it is: {"a": 1} Hope that helps."#, r#"{"a": 1}"#),
+        (r#"{"a": [1, 2,], }"#, r#"{"a": [1, 2]}"#),
+        (r#"{"a": [1, 2"#, r#"{"a": [1, 2]}"#),
+        (r#"{"a": 1,"#, r#"{"a": 1}"#),
+        (r#"{"name": "Par"#, r#"{"name": "Par"}"#),
+        // The escaped quote does not end the string; the closing one is added.
+        (r#"{"quote": "she said \"hi"#, r#"{"quote": "she said \"hi"}"#),
+        // A dangling escape is dropped before the string is closed.
+        (r#"{"a": "x\"#, r#"{"a": "x"}"#),
+        ("no json here", "no json here"),
+    ];
+
+    fn schema() -> OutputSchema {
+        OutputSchema::new(json!({
+            "type": "object",
+            "properties": {"city": {"type": "string"}, "population": {"type": "integer"}},
+            "required": ["city", "population"],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn repairs() {
+        for (output, expected) in REPAIR_CASES {
+            assert_eq!(repair_json(output), *expected, "{:?}", output);
+        }
+    }
+
+    #[test]
+    fn valid_output_is_not_repaired() {
+        let checked = schema().check(r#" {"city": "Paris", "population": 2100000} "#).unwrap();
+        assert!(!checked.repaired);
+        assert_eq!(checked.value["city"], "Paris");
+    }
+
+    #[test]
+    fn repaired_output_is_validated() {
+        let checked = schema().check("```json\n{\"city\": \"Paris\", \"population\": 2100000,\n```").unwrap();
+        assert!(checked.repaired);
+        assert_eq!(checked.text, r#"{"city": "Paris", "population": 2100000}"#);
+    }
+
+    #[test]
+    fn schema_errors_are_returned_when_repair_is_not_enough() {
+        let errors = schema().check(r#"{"city": "Paris", "population": "two million""#).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("/population: "), "{:?}", errors);
+
+        let errors = schema().check("I don't know.").unwrap_err();
+        assert!(errors[0].starts_with("not valid JSON"), "{:?}", errors);
+    }
+
+    #[test]
+    fn invalid_schema_is_rejected() {
+        assert!(OutputSchema::new(json!({"type": "no-such-type"})).is_err());
+    }
+}
@@ -0,0 +1,212 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Tokens consumed by one or more LLM calls.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// At least part of the count is a local estimate rather than what the
+    /// backend reported.
+    pub estimated: bool,
+}
+
+impl TokenUsage {
+    pub fn reported(prompt_tokens: u64, completion_tokens: u64) -> Self {
+        Self { prompt_tokens, completion_tokens, estimated: false }
+    }
+
+    pub fn estimate(prompt: &str, completion: &str) -> Self {
+        Self { prompt_tokens: estimate_tokens(prompt), completion_tokens: estimate_tokens(completion), estimated: true }
+    }
+
+    pub fn add(&mut self, other: &TokenUsage) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.estimated |= other.estimated;
+    }
+}
+
+/// Rough BPE-style token count: one token per punctuation mark and per
+/// four characters of each word. Used when a backend reports no usage.
+pub fn estimate_tokens(text: &str) -> u64 {
+    let mut tokens = 0;
+    let mut word_chars: u64 = 0;
+    for c in text.chars() {
+        if c.is_alphanumeric() {
+            word_chars += 1;
+            continue;
+        }
+        tokens += word_chars.div_ceil(4);
+        word_chars = 0;
+        if !c.is_whitespace() {
+            tokens += 1;
+        }
+    }
+    tokens + word_chars.div_ceil(4)
+}
+
+/// USD per million tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ModelPrice {
+    pub input_per_million: f64,
+    pub output_per_million: f64,
+}
+
+impl ModelPrice {
+    pub fn cost(&self, prompt_tokens: u64, completion_tokens: u64) -> f64 {
+        (prompt_tokens as f64 * self.input_per_million + completion_tokens as f64 * self.output_per_million) / 1_000_000.0
+    }
+}
+
+/// Per-model prices, keyed by model name (or backend name for backends
+/// without one). The `*` entry prices everything else.
+#[derive(Debug, Clone, Default)]
+pub struct PriceTable {
+    prices: HashMap<String, ModelPrice>,
+}
+
+impl PriceTable {
+    /// Read `OPTIMA_PRICES` as `model=input/output,...` in USD per million
+    /// tokens, e.g. `gpt-4o-mini=0.15/0.60,*=0.5/1.5`.
+    pub fn from_env() -> Self {
+        Self::parse(&std::env::var("OPTIMA_PRICES").unwrap_or_default())
+    }
+
+    pub fn parse(spec: &str) -> Self {
+        let prices = spec
+            .split(',')
+            .filter_map(|entry| {
+                let (model, price) = entry.trim().split_once('=')?;
+                let (input, output) = price.split_once('/')?;
+                Some((
+                    model.trim().to_string(),
+                    ModelPrice { input_per_million: input.trim().parse().ok()?, output_per_million: output.trim().parse().ok()? },
+                ))
+            })
+            .collect();
+        Self { prices }
+    }
+
+    pub fn lookup(&self, model: &str) -> Option<ModelPrice> {
+        self.prices.get(model).or_else(|| self.prices.get("*")).copied()
+    }
+}
+
+/// Token usage and money for one request, next to what the same calls
+/// would have cost with the unoptimized prompt.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct CostReport {
+    pub usage: TokenUsage,
+    /// Prompt tokens the same calls would have used without reflection
+    /// trimming and HHTC compression (or, on a cache hit, a fresh call).
+    pub baseline_prompt_tokens: u64,
+    pub cost_usd: f64,
+    pub baseline_cost_usd: f64,
+    /// False when some call's model had no entry in the price table; its
+    /// tokens are counted but cost nothing.
+    pub priced: bool,
+}
+
+impl Default for CostReport {
+    fn default() -> Self {
+        Self { usage: TokenUsage::default(), baseline_prompt_tokens: 0, cost_usd: 0.0, baseline_cost_usd: 0.0, priced: true }
+    }
+}
+
+impl CostReport {
+    /// Account for one LLM call. `extra_baseline_tokens` is how many more
+    /// prompt tokens the unoptimized prompt would have taken.
+    pub fn record_call(&mut self, usage: &TokenUsage, extra_baseline_tokens: u64, price: Option<ModelPrice>) {
+        let baseline_prompt = usage.prompt_tokens + extra_baseline_tokens;
+        self.usage.add(usage);
+        self.baseline_prompt_tokens += baseline_prompt;
+        match price {
+            Some(price) => {
+                self.cost_usd += price.cost(usage.prompt_tokens, usage.completion_tokens);
+                self.baseline_cost_usd += price.cost(baseline_prompt, usage.completion_tokens);
+            }
+            None => self.priced = false,
+        }
+    }
+
+    /// Account for a call the response cache made unnecessary: it costs
+    /// nothing, but counts in full towards the baseline.
+    pub fn record_avoided_call(&mut self, baseline: &TokenUsage, price: Option<ModelPrice>) {
+        self.baseline_prompt_tokens += baseline.prompt_tokens;
+        match price {
+            Some(price) => self.baseline_cost_usd += price.cost(baseline.prompt_tokens, baseline.completion_tokens),
+            None => self.priced = false,
+        }
+    }
+
+    pub fn saved_usd(&self) -> f64 {
+        self.baseline_cost_usd - self.cost_usd
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRICE: ModelPrice = ModelPrice { input_per_million: 2.0, output_per_million: 10.0 };
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-12
+    }
+
+    #[test]
+    fn estimate_counts_word_chunks_and_punctuation() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("a b c"), 3);
+        assert_eq!(estimate_tokens("Hello, world!"), 6);
+        assert_eq!(estimate_tokens("internationalization"), 5);
+        assert!(TokenUsage::estimate("Hi", "Hello").estimated);
+    }
+
+    #[test]
+    fn price_table_skips_malformed_entries() {
+        let table = PriceTable::parse(" gpt-4o-mini = 0.15/0.60 , broken, nodash=1.0, bad=x/1, *=0.5/1.5");
+        assert_eq!(table.lookup("gpt-4o-mini"), Some(ModelPrice { input_per_million: 0.15, output_per_million: 0.60 }));
+        assert_eq!(table.prices.len(), 2);
+        // Unknown and malformed models fall back to the wildcard.
+        assert_eq!(table.lookup("bad"), Some(ModelPrice { input_per_million: 0.5, output_per_million: 1.5 }));
+        assert_eq!(PriceTable::parse("gpt-4o-mini=0.15/0.60").lookup("llama3"), None);
+        assert_eq!(PriceTable::parse("").lookup("llama3"), None);
+    }
+
+    #[test]
+    fn cost_baseline_and_savings() {
+        let mut cost = CostReport::default();
+        cost.record_call(&TokenUsage::reported(1000, 100), 500, Some(PRICE));
+        assert!(close(cost.cost_usd, 0.003));
+        assert!(close(cost.baseline_cost_usd, 0.004));
+        assert_eq!(cost.baseline_prompt_tokens, 1500);
+
+        // A retry adds to both sides; an estimated count taints the total.
+        cost.record_call(&TokenUsage::estimate("word", "word"), 0, Some(PRICE));
+        assert_eq!(cost.usage.prompt_tokens, 1001);
+        assert!(cost.usage.estimated);
+        assert!(close(cost.saved_usd(), 0.001));
+        assert!(cost.priced);
+    }
+
+    #[test]
+    fn avoided_calls_count_only_towards_the_baseline() {
+        let mut cost = CostReport::default();
+        cost.record_avoided_call(&TokenUsage::reported(1000, 100), Some(PRICE));
+        assert_eq!(cost.cost_usd, 0.0);
+        assert_eq!(cost.usage, TokenUsage::default());
+        assert!(close(cost.saved_usd(), 0.003));
+    }
+
+    #[test]
+    fn unpriced_calls_count_tokens_but_no_money() {
+        let mut cost = CostReport::default();
+        cost.record_call(&TokenUsage::reported(1000, 100), 500, None);
+        assert!(!cost.priced);
+        assert_eq!(cost.usage.completion_tokens, 100);
+        assert_eq!(cost.cost_usd, 0.0);
+        assert_eq!(cost.saved_usd(), 0.0);
+    }
+}
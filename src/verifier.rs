@@ -1,29 +1,399 @@
-use crate::ffi;
+use crate::detectors;
+use crate::ekf::RetrievedFact;
+use crate::numeric::{NumericChecker, NumericMismatch};
+use crate::policy::{ContentPolicy, PolicyOutcome, PolicyStage};
+use crate::rules::{RuleHit, RuleStore};
+use crate::structured::{OutputSchema, StructuredOutput};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
 use tracing::warn;
 
+/// Contradiction scores above this reject an output.
+pub const CONTRADICTION_THRESHOLD: f64 = 0.7;
+
+/// What the verifier found in one LLM output, before anything is done
+/// about it.
+#[derive(Debug, Clone, Default)]
+pub struct Assessment {
+    /// `None` when the contradiction check did not run.
+    pub contradiction_score: Option<f64>,
+    pub contradiction: bool,
+    /// Numbers, dates or quantities that disagree with a fact.
+    pub numeric_mismatches: Vec<NumericMismatch>,
+    pub quality_issue: Option<String>,
+}
+
+impl Assessment {
+    /// Why the output should not be accepted as is, if it should not.
+    pub fn failure(&self) -> Option<String> {
+        match self.contradiction_score {
+            Some(score) if self.contradiction => Some(format!("contradiction (score: {:.2})", score)),
+            _ => match self.numeric_mismatches.first() {
+                Some(mismatch) => Some(format!(
+                    "numeric mismatch: output says {} but a fact says {}",
+                    mismatch.output_value, mismatch.fact_value
+                )),
+                None => self.quality_issue.clone(),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClaimVerdict {
+    Supported,
+    Contradicted,
+    /// No retrieved fact covers the claim either way.
+    Unknown,
+}
+
+/// One atomic claim from the output and how it fared against the facts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClaimReport {
+    pub text: String,
+    /// Byte range of the claim in the output, for highlighting.
+    pub start: usize,
+    pub end: usize,
+    pub verdict: ClaimVerdict,
+    /// EKF key of the contradicted fact, or of the supporting one.
+    pub fact_key: Option<String>,
+    /// Contradiction score against `fact_key`'s fact (or the highest
+    /// against any fact when the claim is unknown).
+    pub score: f64,
+    /// The rules behind `score`.
+    pub contributions: Vec<RuleHit>,
+}
+
+/// Claim-by-claim verification of one output.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VerificationReport {
+    pub claims: Vec<ClaimReport>,
+    /// Score of the whole output against all facts; this, not the claims,
+    /// decides whether the output is accepted.
+    pub contradiction_score: f64,
+    pub threshold: f64,
+}
+
+impl VerificationReport {
+    pub fn contradicted(&self) -> impl Iterator<Item = &ClaimReport> {
+        self.claims.iter().filter(|claim| claim.verdict == ClaimVerdict::Contradicted)
+    }
+}
+
+/// Byte ranges of the sentences in `text`, further split at `;` and line
+/// breaks. A `.` only ends a sentence when followed by whitespace, so
+/// decimals stay whole.
+pub fn split_claims(text: &str) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((idx, c)) = chars.next() {
+        let end_of_sentence = matches!(c, '.' | '!' | '?') && chars.peek().is_none_or(|(_, next)| next.is_whitespace());
+        if end_of_sentence || matches!(c, ';' | '\n') {
+            spans.push((start, idx + c.len_utf8()));
+            start = idx + c.len_utf8();
+        }
+    }
+    spans.push((start, text.len()));
+    spans
+        .into_iter()
+        .filter_map(|(start, end)| {
+            let span = &text[start..end];
+            let trimmed = span.trim_start();
+            let start = start + (span.len() - trimmed.len());
+            let end = start + trimmed.trim_end_matches(|c: char| c.is_whitespace() || c == ';').len();
+            (end > start).then_some((start, end))
+        })
+        .collect()
+}
+
+/// Lowercased words of four or more characters, which skips most
+/// function words.
+fn content_words(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() >= 4)
+        .map(|word| word.to_lowercase())
+        .collect()
+}
+
+/// Share of the claim's content words that also occur in the fact.
+fn overlap(claim_words: &HashSet<String>, fact: &str) -> f64 {
+    if claim_words.is_empty() {
+        return 0.0;
+    }
+    let fact_words = content_words(fact);
+    claim_words.intersection(&fact_words).count() as f64 / claim_words.len() as f64
+}
+
+/// Claims sharing at least this share of content words with a fact that
+/// they do not contradict count as supported by it.
+const SUPPORT_OVERLAP: f64 = 0.5;
+
+/// Openings of answers that decline the question instead of answering it.
+const REFUSAL_PHRASES: &[&str] = &[
+    "i'm sorry, but",
+    "i am sorry, but",
+    "i cannot help",
+    "i can't help",
+    "i cannot answer",
+    "i can't answer",
+    "i cannot provide",
+    "i can't provide",
+    "i'm unable to",
+    "i am unable to",
+    "i'm not able to",
+    "i am not able to",
+    "as an ai language model",
+    "as an ai, i",
+];
+
+/// Whether `output` opens with a refusal.
+fn is_refusal(output: &str) -> bool {
+    let opening: String = output.trim_start().chars().take(80).collect::<String>().to_lowercase().replace('\u{2019}', "'");
+    REFUSAL_PHRASES.iter().any(|phrase| opening.starts_with(phrase))
+}
+
+/// A claim that occurs at least three times, ignoring case and spacing.
+fn repeated_claim(output: &str) -> Option<String> {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    for (start, end) in split_claims(output) {
+        let claim = output[start..end].split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+        let count = seen.entry(claim.clone()).or_insert(0);
+        *count += 1;
+        if *count == 3 {
+            return Some(claim);
+        }
+    }
+    None
+}
+
+/// Share of the claims in `output` that share enough content words with
+/// some fact to count as grounded in it; `None` without facts or claims.
+fn support_ratio(output: &str, ekf_knowledge: &[String]) -> Option<f64> {
+    let claims = split_claims(output);
+    if ekf_knowledge.is_empty() || claims.is_empty() {
+        return None;
+    }
+    let supported = claims
+        .iter()
+        .filter(|(start, end)| {
+            let claim_words = content_words(&output[*start..*end]);
+            ekf_knowledge.iter().any(|fact| overlap(&claim_words, fact) >= SUPPORT_OVERLAP)
+        })
+        .count();
+    Some(supported as f64 / claims.len() as f64)
+}
+
 pub struct Verifier {
     contradiction_threshold: f64,
+    min_output_chars: usize,
+    /// 0 disables the claim-support check.
+    min_support_ratio: f64,
+    rules: Arc<RuleStore>,
+    /// `None` when `OPTIMA_NUMERIC_CHECK=off`.
+    numeric: Option<NumericChecker>,
+    policy: Option<ContentPolicy>,
 }
 
 impl Verifier {
-    pub fn new() -> Self {
-        Self {
-            contradiction_threshold: 0.7, 
+    /// Loads rule packs (see `RuleStore::from_env`) and re-checks them for
+    /// changes every `OPTIMA_RULES_RELOAD_SECS` (default 5; 0 disables).
+    /// Reloading needs a Tokio runtime; created outside one, the verifier
+    /// keeps the packs it loaded.
+    pub fn new() -> Result<Self, Box<dyn Error>> {
+        let rules = Arc::new(RuleStore::from_env()?);
+        let reload_secs: u64 = std::env::var("OPTIMA_RULES_RELOAD_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(5);
+        if reload_secs > 0 {
+            match tokio::runtime::Handle::try_current() {
+                Ok(runtime) => {
+                    runtime.spawn(rules.clone().watch(Duration::from_secs(reload_secs)));
+                }
+                Err(_) => warn!("No Tokio runtime; rule packs will not be reloaded when they change."),
+            }
         }
+        Ok(Self {
+            contradiction_threshold: CONTRADICTION_THRESHOLD,
+            min_output_chars: std::env::var("OPTIMA_MIN_OUTPUT_CHARS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(1),
+            min_support_ratio: std::env::var("OPTIMA_MIN_SUPPORT_RATIO")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(0.0),
+            rules,
+            numeric: match std::env::var("OPTIMA_NUMERIC_CHECK").as_deref() {
+                Ok("off") | Ok("false") | Ok("0") => None,
+                _ => Some(NumericChecker::from_env()),
+            },
+            policy: ContentPolicy::from_env()?,
+        })
     }
     
-    pub async fn verify_and_rollback(&self, output: &str, ekf_knowledge: &[String]) -> String {
-        if ekf_knowledge.is_empty() {
-            return output.to_string();
+    pub fn contradiction_threshold(&self) -> f64 {
+        self.contradiction_threshold
+    }
+
+    /// Contradiction score of `output` against `ekf_knowledge`.
+    pub async fn contradiction_score(&self, output: &str, ekf_knowledge: &[String]) -> f64 {
+        let rules = self.rules.current();
+        detectors::check_for_contradiction(output, ekf_knowledge, &rules).await
+    }
+
+    /// Run the content policy over a prompt or an answer; `None` when no
+    /// policy is configured.
+    pub fn apply_policy(&self, text: &str, stage: PolicyStage) -> Option<PolicyOutcome> {
+        self.policy.as_ref().map(|policy| policy.apply(text, stage))
+    }
+
+    /// Whether the content policy has output rules that block or redact.
+    pub fn policy_rewrites_output(&self) -> bool {
+        self.policy.as_ref().is_some_and(ContentPolicy::rewrites_output)
+    }
+
+    /// Whether `output` is JSON matching `schema`, after a local repair if
+    /// needed. Errors are phrased for feeding back to the model.
+    pub fn check_structure(&self, output: &str, schema: &OutputSchema) -> Result<StructuredOutput, Vec<String>> {
+        schema.check(output)
+    }
+
+    /// Values in `output` that disagree with the same-subject value in any
+    /// fact, claim by claim, paired with the index of that fact.
+    pub fn numeric_mismatches(&self, output: &str, ekf_knowledge: &[String]) -> Vec<(usize, NumericMismatch)> {
+        let Some(numeric) = &self.numeric else {
+            return Vec::new();
+        };
+        split_claims(output)
+            .into_iter()
+            .flat_map(|(start, end)| {
+                let claim = &output[start..end];
+                ekf_knowledge
+                    .iter()
+                    .enumerate()
+                    .flat_map(move |(index, fact)| numeric.compare(claim, fact).into_iter().map(move |mismatch| (index, mismatch)))
+            })
+            .collect()
+    }
+
+    /// Split `output` into claims and check each against every fact with
+    /// the current rule packs.
+    pub async fn report(&self, output: &str, facts: &[RetrievedFact]) -> VerificationReport {
+        let values: Vec<String> = facts.iter().map(|fact| fact.value.clone()).collect();
+        let contradiction_score = self.contradiction_score(output, &values).await;
+        let rules = self.rules.current();
+
+        let claims = split_claims(output)
+            .into_iter()
+            .map(|(start, end)| {
+                let text = &output[start..end];
+                let scored: Vec<(&RetrievedFact, f64, Vec<RuleHit>)> = facts
+                    .iter()
+                    .map(|fact| {
+                        let hits = rules.evaluate(text, std::slice::from_ref(&fact.value));
+                        let score = f64::min(hits.iter().fold(0.0, |score, hit| score + hit.weight), 1.0);
+                        (fact, score, hits)
+                    })
+                    .collect();
+                let worst = scored.iter().max_by(|a, b| a.1.total_cmp(&b.1));
+                let numeric_conflict = self.numeric.as_ref().and_then(|numeric| {
+                    scored.iter().find_map(|(fact, _, hits)| {
+                        let mismatch = numeric.compare(text, &fact.value).into_iter().next()?;
+                        Some((*fact, mismatch, hits))
+                    })
+                });
+
+                let claim_words = content_words(text);
+                let supporting = scored
+                    .iter()
+                    .filter(|(_, score, _)| *score <= self.contradiction_threshold)
+                    .map(|(fact, score, hits)| (overlap(&claim_words, &fact.value), fact, score, hits))
+                    .filter(|(overlap, _, _, _)| *overlap >= SUPPORT_OVERLAP)
+                    .max_by(|a, b| a.0.total_cmp(&b.0));
+
+                let (verdict, fact_key, score, contributions) = match (worst, numeric_conflict, supporting) {
+                    (Some((fact, score, hits)), _, _) if *score > self.contradiction_threshold => {
+                        (ClaimVerdict::Contradicted, Some(fact.key.clone()), *score, hits.clone())
+                    }
+                    (_, Some((fact, mismatch, hits)), _) => {
+                        let mut contributions = hits.clone();
+                        contributions.push(RuleHit {
+                            pack: "numeric".to_string(),
+                            rule: format!("'{}' vs '{}'", mismatch.output_value, mismatch.fact_value),
+                            weight: 1.0,
+                        });
+                        (ClaimVerdict::Contradicted, Some(fact.key.clone()), 1.0, contributions)
+                    }
+                    (_, None, Some((_, fact, score, hits))) => (ClaimVerdict::Supported, Some(fact.key.clone()), *score, hits.clone()),
+                    (Some((_, score, hits)), None, None) => (ClaimVerdict::Unknown, None, *score, hits.clone()),
+                    (None, None, None) => (ClaimVerdict::Unknown, None, 0.0, Vec::new()),
+                };
+                ClaimReport { text: text.to_string(), start, end, verdict, fact_key, score, contributions }
+            })
+            .collect();
+
+        VerificationReport { claims, contradiction_score, threshold: self.contradiction_threshold }
+    }
+
+    /// Cheap checks that need no rule packs: outputs shorter than
+    /// `OPTIMA_MIN_OUTPUT_CHARS` (default 1), refusals, a sentence repeated
+    /// three or more times, and, when `OPTIMA_MIN_SUPPORT_RATIO` is set
+    /// (default 0, off), too few claims grounded in `ekf_knowledge`.
+    pub fn quality_issue(&self, output: &str, ekf_knowledge: &[String]) -> Option<String> {
+        let length = output.trim().chars().count();
+        if length == 0 {
+            return Some("empty output".to_string());
         }
-        
-        let contradiction_score = ffi::check_for_contradiction(output, ekf_knowledge).await;
-        
-        if contradiction_score > self.contradiction_threshold {
-            warn!("Verification failed: Contradiction detected (score: {:.2}). Rolling back...", contradiction_score);
-            format!("Rollback triggered: The generated output contained a contradiction and was re-run. Here is a corrected response. Original contradiction score: {:.2}", contradiction_score)
+        if length < self.min_output_chars {
+            return Some(format!("output shorter than {} characters", self.min_output_chars));
+        }
+        if is_refusal(output) {
+            return Some("output is a refusal".to_string());
+        }
+        if let Some(claim) = repeated_claim(output) {
+            return Some(format!("output repeats '{}'", claim));
+        }
+        match support_ratio(output, ekf_knowledge) {
+            Some(ratio) if ratio < self.min_support_ratio => {
+                Some(format!("only {:.0}% of claims are supported by the retrieved facts", ratio * 100.0))
+            }
+            _ => None,
+        }
+    }
+
+    pub async fn assess(&self, output: &str, ekf_knowledge: &[String]) -> Assessment {
+        let contradiction_score = if ekf_knowledge.is_empty() {
+            None
+        } else {
+            Some(self.contradiction_score(output, ekf_knowledge).await)
+        };
+        Assessment {
+            contradiction_score,
+            contradiction: contradiction_score.is_some_and(|score| score > self.contradiction_threshold),
+            numeric_mismatches: self.numeric_mismatches(output, ekf_knowledge).into_iter().map(|(_, mismatch)| mismatch).collect(),
+            quality_issue: self.quality_issue(output, ekf_knowledge),
+        }
+    }
+
+    /// The facts `output` contradicts when checked one at a time, or whose
+    /// numbers it gets wrong. Falls back to all of `ekf_knowledge` when no
+    /// single fact stands out.
+    pub async fn offending_facts(&self, output: &str, ekf_knowledge: &[String]) -> Vec<String> {
+        let mismatched: HashSet<usize> = self.numeric_mismatches(output, ekf_knowledge).into_iter().map(|(index, _)| index).collect();
+        let mut offending = Vec::new();
+        for (index, fact) in ekf_knowledge.iter().enumerate() {
+            let score = self.contradiction_score(output, std::slice::from_ref(fact)).await;
+            if score > self.contradiction_threshold || mismatched.contains(&index) {
+                offending.push(fact.clone());
+            }
+        }
+        if offending.is_empty() {
+            ekf_knowledge.to_vec()
         } else {
-            output.to_string()
+            offending
         }
     }
     
@@ -42,4 +412,34 @@ impl Verifier {
         
         trimmed_text.trim().to_string()
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_works_outside_a_runtime() {
+        assert!(Verifier::new().is_ok());
+    }
+
+    #[test]
+    fn refusals_are_recognised_at_the_start_only() {
+        assert!(is_refusal("I’m sorry, but I can't share that."));
+        assert!(is_refusal("  As an AI language model, I do not have opinions."));
+        assert!(!is_refusal("Paris is the capital. I'm sorry, but that is all I know."));
+        assert!(!is_refusal("I cannot stress enough how large Paris is."));
+    }
+
+    #[test]
+    fn three_copies_of_a_sentence_are_repetition() {
+        assert_eq!(repeated_claim("It is blue. It is  blue. it is blue."), Some("it is blue.".to_string()));
+        assert_eq!(repeated_claim("It is blue. It is blue. It is red."), None);
+    }
+
+    #[test]
+    fn support_ratio_counts_grounded_claims() {
+        let facts = vec!["Paris is the capital city of France".to_string()];
+        assert_eq!(support_ratio("Paris is the capital city of France. Bananas grow quickly.", &facts), Some(0.5));
+        assert_eq!(support_ratio("Anything at all.", &[]), None);
+    }
+}
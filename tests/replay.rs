@@ -0,0 +1,111 @@
+use optimacore::core::OptimaCore;
+use optimacore::request::RequestOptions;
+use optimacore::rollback::{RollbackPolicy, VerificationError};
+use optimacore::streaming::StreamEvent;
+use std::path::Path;
+
+/// A core answering from the checked-in fixtures through a three-tier
+/// cascade: `small` replays `replay.json`, `medium` and `large` replay
+/// `replay_large.json`. Every test sets the same environment, so running
+/// them concurrently is harmless.
+async fn replay_core(name: &str) -> OptimaCore {
+    let data = Path::new(env!("CARGO_TARGET_TMPDIR")).join("replay");
+    let large = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/replay_large.json");
+    std::env::set_var("LLM_BACKEND", "replay");
+    std::env::set_var("LLM_CASCADE_TIERS", "small,medium,large");
+    std::env::set_var("LLM_FIXTURE_PATH", concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/replay.json"));
+    std::env::set_var("LLM_MEDIUM_FIXTURE_PATH", large);
+    std::env::set_var("LLM_LARGE_FIXTURE_PATH", large);
+    std::env::set_var("OPTIMA_ROLLBACK_POLICIES", "stronger_backend,lower_temperature,no_compression");
+    std::env::set_var("OPTIMA_ROLLBACK_MAX_ATTEMPTS", "3");
+    std::env::set_var("OPTIMA_DATA_DIR", &data);
+    std::env::set_var("OPTIMA_RESPONSE_CACHE", "off");
+    std::env::set_var("OPTIMA_RULES_RELOAD_SECS", "0");
+    let ekf_path = data.join(name);
+    let _ = std::fs::remove_dir_all(&ekf_path);
+    OptimaCore::new(&ekf_path).await.unwrap()
+}
+
+/// Reflection trimming must leave the prompt exactly as it was when the
+/// fixture was recorded, or replay finds no response for it.
+#[tokio::test]
+async fn process_request_replays_recorded_response() {
+    let mut core = replay_core("recorded").await;
+    let response = core.process_request("What is the capital of France? Think about it.").await.unwrap();
+
+    assert_eq!(response.output, "The capital of France is Paris.");
+    assert!(response.reflection_trimmed);
+    assert!(!response.cache_hit);
+    assert_eq!(response.answered_by.as_deref(), Some("small"));
+    assert_eq!(response.cost.usage.prompt_tokens, 9);
+    assert_eq!(response.cost.usage.completion_tokens, 7);
+    assert!(!response.cost.usage.estimated);
+    assert_eq!(response.passed_attempt, Some(0));
+}
+
+#[tokio::test]
+async fn unrecorded_prompt_is_an_error() {
+    let mut core = replay_core("unrecorded").await;
+    let error = core.process_request("What is the capital of Spain?").await.unwrap_err();
+    assert!(error.to_string().contains("No recorded LLM response"), "{}", error);
+}
+
+/// The stream sample must be recorded by the time the receiver closes, so a
+/// caller that flushes stats right after the stream loses nothing.
+#[tokio::test]
+async fn streamed_request_is_recorded_before_the_stream_closes() {
+    let mut core = replay_core("streamed").await;
+    let before = core.stats_snapshot().totals.requests;
+    let mut events = core
+        .process_request_stream("What is the capital of France? Think about it.", &RequestOptions::default())
+        .await
+        .unwrap();
+    let mut last = None;
+    while let Some(event) = events.recv().await {
+        last = Some(event);
+    }
+
+    assert!(matches!(last, Some(StreamEvent::Done { ref output, .. }) if output == "The capital of France is Paris."), "{:?}", last);
+    assert_eq!(core.stats_snapshot().totals.requests, before + 1);
+}
+
+fn policies(attempts: &[optimacore::rollback::AttemptRecord]) -> Vec<(&str, Option<RollbackPolicy>)> {
+    attempts.iter().map(|attempt| (attempt.tier.as_str(), attempt.policy)).collect()
+}
+
+/// `small` refuses; the escalation to `medium` answers.
+#[tokio::test]
+async fn rejected_attempt_escalates_and_passes() {
+    let mut core = replay_core("escalates").await;
+    let response = core.process_request("Who wrote Hamlet?").await.unwrap();
+
+    assert_eq!(response.output, "Hamlet was written by William Shakespeare.");
+    assert_eq!(response.passed_attempt, Some(1));
+    assert_eq!(response.escalations, 1);
+    assert_eq!(response.answered_by.as_deref(), Some("medium"));
+    assert_eq!(policies(&response.attempts), [("small", None), ("medium", Some(RollbackPolicy::StrongerBackend))]);
+    assert!(response.attempts[0].rejected.as_deref().is_some_and(|reason| reason.contains("refus")), "{:?}", response.attempts[0]);
+    assert!(response.attempts[1].rejected.is_none());
+}
+
+/// Every tier refuses. `StrongerBackend` repeats up to `large`, the lower
+/// temperature is applied on top of it, and `max_attempts` ends the loop
+/// before `no_compression` is reached.
+#[tokio::test]
+async fn every_attempt_rejected_is_a_verification_error() {
+    let mut core = replay_core("exhausted").await;
+    let error = core.process_request("What is the launch code?").await.unwrap_err();
+    let error = error.downcast_ref::<VerificationError>().expect("a VerificationError");
+
+    assert_eq!(
+        policies(&error.attempts),
+        [
+            ("small", None),
+            ("medium", Some(RollbackPolicy::StrongerBackend)),
+            ("large", Some(RollbackPolicy::StrongerBackend)),
+            ("large", Some(RollbackPolicy::LowerTemperature)),
+        ]
+    );
+    assert!(error.attempts.iter().all(|attempt| attempt.rejected.is_some()));
+    assert_eq!(error.last_output, "I'm sorry, but I can't help with that.");
+}